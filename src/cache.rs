@@ -25,7 +25,11 @@ async fn read_cache_index<P: AsRef<Path>>(path: P) -> io::Result<Index> {
 async fn write_cache_index<P: AsRef<Path>>(path: P, index: &Index) -> io::Result<()> {
     let path = path.as_ref();
     let bytes = serde_json::to_vec(index).expect("malformed cache index");
-    fs::write(path, bytes).await
+
+    // write to a temporary file first so that concurrent readers never observe a partial index
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, bytes).await?;
+    fs::rename(temp_path, path).await
 }
 
 pub struct Loader {
@@ -54,13 +58,12 @@ impl Loader {
         Ok(Loader { root, old_entries, entries, used_entries: HashSet::new() })
     }
 
-    pub fn entry<K: Into<String>>(&mut self, key: K) -> Entry {
+    pub fn entry<K: Into<String>>(&mut self, key: K) -> Entry<'_> {
         let key = key.into();
-        let current_token = self
-            .entries
-            .get(&key)
-            .map(|entry| entry.token.clone())
-            .unwrap_or(Token::Unknown);
+        let (current_token, rejected_token) = match self.entries.get(&key) {
+            Some(entry) => (entry.token.clone(), entry.rejected.clone()),
+            None => (Token::Unknown, None),
+        };
 
         self.used_entries.insert(key.clone());
 
@@ -68,9 +71,73 @@ impl Loader {
             loader: self,
             key,
             current_token,
+            rejected_token,
+            internal: false,
         }
     }
 
+    /// Returns whether the entry with the given key is already up-to-date with the given token.
+    pub fn is_current(&self, key: &str, token: &Token) -> bool {
+        self.entries
+            .get(key)
+            .map(|entry| &entry.token == token || entry.rejected.as_ref() == Some(token))
+            .unwrap_or(false)
+    }
+
+    /// Restores the previous version of the entry with the given key, rejecting the current version
+    /// until a newer one is available. Returns the references to the removed and restored files.
+    pub async fn rollback(&mut self, key: &str) -> io::Result<Option<(Reference, Reference)>> {
+        let path = self.path_for(key);
+        let previous_path = self.previous_path_for(key);
+
+        let entry = match self.entries.get_mut(key) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let previous = match entry.previous.take() {
+            Some(previous) => previous,
+            None => return Ok(None),
+        };
+
+        fs::rename(&previous_path, &path).await?;
+
+        let removed = Reference {
+            path: path.clone(),
+            name: std::mem::replace(&mut entry.file_name, previous.file_name.clone()),
+            token: entry.token.clone(),
+            changed: false,
+            internal: entry.internal,
+        };
+        entry.rejected = Some(std::mem::replace(&mut entry.token, previous.token.clone()));
+        entry.unconfirmed = false;
+
+        let restored = Reference {
+            path,
+            name: previous.file_name,
+            token: previous.token,
+            changed: true,
+            internal: entry.internal,
+        };
+
+        Ok(Some((removed, restored)))
+    }
+
+    /// Marks the version of the entry with the given key and token as known to work, so that it is kept as the
+    /// previous version once it is replaced. Nothing happens if the entry has been updated to another version since.
+    pub fn confirm(&mut self, key: &str, token: &Token) {
+        if let Some(entry) = self.entries.get_mut(key) {
+            if &entry.token == token {
+                entry.unconfirmed = false;
+            }
+        }
+    }
+
+    /// Writes the index without removing any entries, for when the loader was not used to load sources.
+    pub async fn save(self) -> io::Result<()> {
+        let entries = self.entries.into_values().collect();
+        write_cache_index(&self.root.join("index.json"), &Index { entries }).await
+    }
+
     pub async fn close(mut self) -> io::Result<Vec<Reference>> {
         let stale_entries: Vec<String> = self
             .entries
@@ -82,10 +149,14 @@ impl Loader {
             let entry = self.entries.remove(&key).unwrap();
             let reference = self.reference_for(&entry);
             fs::remove_file(&reference.path).await?;
+
+            if entry.previous.is_some() {
+                fs::remove_file(self.previous_path_for(&key)).await?;
+            }
         }
 
         let old_files = self.old_entries.clone().values()
-            .map(|entry| self.reference_for(&entry))
+            .map(|entry| self.reference_for(entry))
             .collect();
 
         let entries = self.entries.into_values().collect();
@@ -99,33 +170,59 @@ impl Loader {
         key: String,
         token: Token,
         name: String,
+        internal: bool,
         bytes: &[u8],
     ) -> io::Result<Reference> {
         let path = self.path_for(&key);
-
-        fs::write(&path, bytes).await?;
+        let previous_path = self.previous_path_for(&key);
 
         use std::collections::hash_map::Entry::*;
 
         match self.entries.entry(key.clone()) {
             Occupied(mut occupied) => {
                 let occupied = occupied.get_mut();
-                occupied.token = token;
+
+                // keep the current version around so that it can be restored if the new version is broken,
+                // unless it never ran successfully: then the version before it is still the one to restore
+                if path.exists() && !occupied.unconfirmed {
+                    if let Some(parent) = previous_path.parent() {
+                        fs::create_dir_all(parent).await?;
+                    }
+                    fs::rename(&path, &previous_path).await?;
+
+                    occupied.previous = Some(PreviousEntry {
+                        token: occupied.token.clone(),
+                        file_name: occupied.file_name.clone(),
+                    });
+                }
+
+                occupied.token = token.clone();
                 occupied.file_name = name.clone();
+                occupied.rejected = None;
+                occupied.unconfirmed = true;
+                occupied.internal = internal;
             }
             Vacant(vacant) => {
                 vacant.insert(IndexEntry {
                     key,
-                    token,
+                    token: token.clone(),
                     file_name: name.clone(),
+                    previous: None,
+                    rejected: None,
+                    unconfirmed: true,
+                    internal,
                 });
             }
         }
 
+        fs::write(&path, bytes).await?;
+
         Ok(Reference {
             path,
             name,
+            token,
             changed: true,
+            internal,
         })
     }
 
@@ -139,32 +236,87 @@ impl Loader {
         Reference {
             path,
             name,
+            token: entry.token.clone(),
             changed: false,
+            internal: entry.internal,
         }
     }
 
     #[inline]
     fn path_for(&self, key: &str) -> PathBuf {
-        self.root.join(key)
+        self.root.join(file_name_for(key))
+    }
+
+    #[inline]
+    fn previous_path_for(&self, key: &str) -> PathBuf {
+        self.root.join(".previous").join(file_name_for(key))
     }
 }
 
+/// Keys of sources that load multiple files are nested like `pack/mods/a.jar`, but are kept flat within the cache.
+fn file_name_for(key: &str) -> String {
+    key.replace('%', "%25").replace('/', "%2F")
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 struct IndexEntry {
     key: String,
     token: Token,
     file_name: String,
+    /// The version that was replaced by the current version, if it is still available.
+    #[serde(default = "Default::default", skip_serializing_if = "Option::is_none")]
+    previous: Option<PreviousEntry>,
+    /// A version that was rolled back and should not be loaded again.
+    #[serde(default = "Default::default", skip_serializing_if = "Option::is_none")]
+    rejected: Option<Token>,
+    /// Whether the current version has not been confirmed to work by a successful run of the server yet.
+    #[serde(default = "Default::default", skip_serializing_if = "std::ops::Not::not")]
+    unconfirmed: bool,
+    /// Whether this entry is only used to load other entries and should not be copied into the destination.
+    #[serde(default = "Default::default", skip_serializing_if = "std::ops::Not::not")]
+    internal: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct PreviousEntry {
+    token: Token,
+    file_name: String,
 }
 
 pub struct Entry<'a> {
     loader: &'a mut Loader,
     key: String,
     current_token: Token,
+    rejected_token: Option<Token>,
+    internal: bool,
 }
 
 impl<'a> Entry<'a> {
+    /// Marks this entry as only being used to load other entries, such as the archive of a modpack.
+    pub fn internal(mut self) -> Self {
+        self.internal = true;
+        self
+    }
+
+    /// The token of the version that is currently cached, or `Unknown` if nothing is cached.
+    pub fn current_token(&self) -> &Token {
+        &self.current_token
+    }
+
+    /// Whether a version of this entry is cached and its file has not gone missing since.
+    pub fn is_cached(&self) -> bool {
+        self.loader.entries.contains_key(&self.key) && self.loader.path_for(&self.key).exists()
+    }
+
     pub fn try_update(self, token: Token) -> UpdateResult<'a> {
-        if self.current_token != token {
+        if self.rejected_token.as_ref() == Some(&token) {
+            println!(
+                "[{}] cache rejected {:?}, keeping rolled back version",
+                self.key, token
+            );
+            let reference = self.loader.get_reference(&self.key).unwrap();
+            UpdateResult::Match(reference)
+        } else if self.current_token != token || !self.is_cached() {
             println!(
                 "[{}] cache mismatched! new: {:?}, old: {:?}",
                 self.key, token, self.current_token
@@ -183,7 +335,7 @@ impl<'a> Entry<'a> {
 
     async fn update(&mut self, token: Token, name: String, bytes: &[u8]) -> io::Result<Reference> {
         self.loader
-            .update_entry(self.key.clone(), token, name, bytes)
+            .update_entry(self.key.clone(), token, name, self.internal, bytes)
             .await
     }
 }
@@ -191,18 +343,28 @@ impl<'a> Entry<'a> {
 pub struct Reference {
     path: PathBuf,
     name: String,
+    token: Token,
     changed: bool,
+    internal: bool,
 }
 
 impl Reference {
     pub async fn copy_to<P: AsRef<Path>>(&self, root: P) -> io::Result<()> {
-        fs::copy(&self.path, self.resolve_target_path(root)).await?;
+        if self.internal {
+            return Ok(());
+        }
+
+        let target = self.resolve_target_path(root);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::copy(&self.path, target).await?;
         Ok(())
     }
 
     pub async fn remove_from<P: AsRef<Path>>(&self, root: P) -> io::Result<()> {
         let target = self.resolve_target_path(root);
-        if target.exists() {
+        if !self.internal && target.exists() {
             fs::remove_file(target).await
         } else {
             Ok(())
@@ -213,9 +375,18 @@ impl Reference {
         root.as_ref().join(&self.name)
     }
 
+    pub async fn read(&self) -> io::Result<Vec<u8>> {
+        fs::read(&self.path).await
+    }
+
     pub fn changed(&self) -> bool {
         self.changed
     }
+
+    /// The token of the version this reference points to.
+    pub fn token(&self) -> &Token {
+        &self.token
+    }
 }
 
 pub struct EntryUpdater<'a> {
@@ -240,12 +411,26 @@ pub enum UpdateResult<'a> {
 pub enum Token {
     #[serde(rename = "etag")]
     Etag(String),
+    #[serde(rename = "last_modified")]
+    LastModified(String),
     #[serde(rename = "artifact")]
     ArtifactId(usize),
+    #[serde(rename = "asset")]
+    AssetId(usize),
     #[serde(rename = "sha1")]
     Sha1([u8; 20]),
+    #[serde(rename = "sha256")]
+    Sha256(String),
     #[serde(rename = "sha512")]
     Sha512(String),
+    #[serde(rename = "fingerprint")]
+    Fingerprint(u32),
+    #[serde(rename = "version")]
+    Version(String),
+    #[serde(rename = "git")]
+    Git(String),
+    #[serde(rename = "build")]
+    Build(u64, String),
     #[serde(rename = "unknown")]
     Unknown,
 }
@@ -255,12 +440,99 @@ impl PartialEq for Token {
         use Token::*;
         match (self, right) {
             (Etag(left), Etag(right)) => left == right,
+            (LastModified(left), LastModified(right)) => left == right,
             (ArtifactId(left), ArtifactId(right)) => left == right,
+            (AssetId(left), AssetId(right)) => left == right,
             (Sha1(left), Sha1(right)) => left == right,
+            (Sha256(left), Sha256(right)) => left == right,
             (Sha512(left), Sha512(right)) => left == right,
+            (Fingerprint(left), Fingerprint(right)) => left == right,
+            (Version(left), Version(right)) => left == right,
+            (Git(left), Git(right)) => left == right,
+            (Build(left_number, left_path), Build(right_number, right_path)) => {
+                left_number == right_number && left_path == right_path
+            }
             (_, _) => false,
         }
     }
 }
 
 impl Eq for Token {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixture::{seed, TempDir};
+
+    fn version(version: &str) -> Token {
+        Token::Version(version.to_owned())
+    }
+
+    async fn update(loader: &mut Loader, name: &str) {
+        seed(loader, "mod", version(name), name).await;
+    }
+
+    async fn rollback(loader: &mut Loader) -> String {
+        let (_, restored) = loader.rollback("mod").await.unwrap().unwrap();
+        String::from_utf8(restored.read().await.unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn rollback_restores_confirmed_version() {
+        let root = TempDir::new("cache-confirmed");
+        let mut loader = Loader::open(root.path()).await.unwrap();
+
+        update(&mut loader, "1").await;
+        loader.confirm("mod", &version("1"));
+        update(&mut loader, "2").await;
+
+        assert_eq!(rollback(&mut loader).await, "1");
+        assert!(loader.is_current("mod", &version("1")));
+        assert!(loader.is_current("mod", &version("2")));
+    }
+
+    #[tokio::test]
+    async fn rollback_skips_versions_that_never_ran() {
+        let root = TempDir::new("cache-unconfirmed");
+        let mut loader = Loader::open(root.path()).await.unwrap();
+
+        update(&mut loader, "1").await;
+        loader.confirm("mod", &version("1"));
+        update(&mut loader, "2").await;
+        update(&mut loader, "3").await;
+
+        assert_eq!(rollback(&mut loader).await, "1");
+        assert!(!loader.is_current("mod", &version("2")));
+    }
+
+    #[tokio::test]
+    async fn unconfirmed_versions_cannot_be_rolled_back_to() {
+        let root = TempDir::new("cache-first");
+        let mut loader = Loader::open(root.path()).await.unwrap();
+
+        update(&mut loader, "1").await;
+        update(&mut loader, "2").await;
+
+        assert!(loader.rollback("mod").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn update_staged_during_run_then_crash_loop_restores_running_version() {
+        let root = TempDir::new("cache-staged");
+        let mut loader = Loader::open(root.path()).await.unwrap();
+
+        update(&mut loader, "1").await;
+        loader.confirm("mod", &version("1"));
+
+        // version 2 runs for long enough to be confirmed, then version 3 is staged while it is still running
+        update(&mut loader, "2").await;
+        loader.confirm("mod", &version("2"));
+        update(&mut loader, "3").await;
+
+        // confirming version 2 again once it exits must not confirm the staged version that never ran
+        loader.confirm("mod", &version("2"));
+        update(&mut loader, "4").await;
+
+        assert_eq!(rollback(&mut loader).await, "2");
+    }
+}
@@ -8,19 +8,28 @@ use tokio::fs::File;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 pub use destinations::*;
+pub use task::*;
 
 mod destinations;
+mod task;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
-    pub run: Vec<String>,
+    #[serde(deserialize_with = "deserialize_tasks")]
+    pub run: Vec<Task>,
+    #[serde(default = "default_min_restart_interval")]
+    pub min_restart_interval_seconds: u64,
     #[serde(default = "Default::default")]
     pub status: Status,
     #[serde(default = "Default::default")]
     pub tokens: Tokens,
     pub triggers: HashMap<String, Trigger>,
-    #[serde(default = "default_min_restart_interval")]
-    pub min_restart_interval_seconds: u64,
+    #[serde(default = "Default::default")]
+    pub restart: Restart,
+    #[serde(default = "Default::default")]
+    pub shutdown: Shutdown,
+    #[serde(default = "Default::default")]
+    pub crash_loop: CrashLoop,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -31,6 +40,105 @@ pub struct Status {
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Tokens {
     pub github: Option<String>,
+    pub curseforge: Option<String>,
+    pub gitlab: Option<String>,
+    #[serde(alias = "forgejo")]
+    pub gitea: Option<String>,
+    /// Falls back to the `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY` environment variables when not given.
+    pub s3: Option<S3Credentials>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct S3Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Restart {
+    #[serde(default = "Default::default")]
+    pub policy: RestartPolicy,
+    /// The maximum number of restarts allowed within `window_seconds` before the wrapper gives up.
+    /// A value of 0 allows unlimited restarts.
+    #[serde(default = "default_max_restarts")]
+    pub max_restarts: u32,
+    #[serde(default = "default_restart_window")]
+    pub window_seconds: u64,
+    #[serde(default = "default_max_backoff")]
+    pub max_backoff_seconds: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum RestartPolicy {
+    /// Restart the server whenever it exits.
+    #[serde(rename = "always")]
+    #[default]
+    Always,
+    /// Restart the server only when it exits unsuccessfully.
+    #[serde(rename = "on-failure")]
+    OnFailure,
+    /// Exit the wrapper together with the server, leaving restarts to a hosting panel such as Pterodactyl.
+    #[serde(rename = "never")]
+    Never,
+}
+
+impl Default for Restart {
+    fn default() -> Self {
+        Restart {
+            policy: RestartPolicy::default(),
+            max_restarts: default_max_restarts(),
+            window_seconds: default_restart_window(),
+            max_backoff_seconds: default_max_backoff(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Shutdown {
+    /// How many seconds before stopping the server players should be warned, e.g. `[300, 60, 10]`.
+    #[serde(default = "default_shutdown_warnings")]
+    pub warnings: Vec<u64>,
+    /// The console command used to warn players, where `{time}` is replaced with the time remaining.
+    #[serde(default = "default_warning_command")]
+    pub warning_command: String,
+    #[serde(default = "default_stop_command")]
+    pub stop_command: String,
+    /// How long to wait for the server to exit after sending the stop command before killing it.
+    #[serde(default = "default_grace_period")]
+    pub grace_period_seconds: u64,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Shutdown {
+            warnings: default_shutdown_warnings(),
+            warning_command: default_warning_command(),
+            stop_command: default_stop_command(),
+            grace_period_seconds: default_grace_period(),
+        }
+    }
+}
+
+/// Detects a server that keeps crashing shortly after starting, and rolls back any sources that changed
+/// since it last ran successfully.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrashLoop {
+    /// A failure within this many seconds of starting counts towards a crash loop.
+    #[serde(default = "default_fast_exit")]
+    pub fast_exit_seconds: u64,
+    /// How many consecutive fast failures are considered a crash loop. A value of 0 disables rollbacks.
+    #[serde(default = "default_max_fast_failures")]
+    pub max_failures: u32,
+}
+
+impl Default for CrashLoop {
+    fn default() -> Self {
+        CrashLoop {
+            fast_exit_seconds: default_fast_exit(),
+            max_failures: default_max_fast_failures(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,13 +147,45 @@ pub enum Trigger {
     #[serde(rename = "startup")]
     Startup,
     #[serde(rename = "webhook")]
-    Webhook { port: u16 },
+    Webhook {
+        port: u16,
+        /// An optional secret that requests must provide as a bearer token in the `Authorization` header.
+        token: Option<String>,
+        #[serde(default = "Default::default")]
+        action: TriggerAction,
+    },
+    #[serde(rename = "poll")]
+    Poll {
+        interval_seconds: u64,
+        #[serde(default = "Default::default")]
+        action: TriggerAction,
+    },
+    #[serde(rename = "schedule")]
+    Schedule {
+        /// A cron expression in local time, such as `0 4 * * *` for every day at 4am.
+        /// An optional leading seconds field is also accepted.
+        cron: String,
+        #[serde(default = "Default::default")]
+        action: TriggerAction,
+    },
+}
+
+/// Declares what should happen after a trigger has refreshed its destinations while the server is running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum TriggerAction {
+    /// Restart the server to apply the refreshed files immediately.
+    #[serde(rename = "restart")]
+    #[default]
+    Restart,
+    /// Keep the refreshed files staged until the server next restarts.
+    #[serde(rename = "stage")]
+    Stage,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
-            run: vec!["java -jar fabric-server-launch.jar".to_owned()],
+            run: vec![Task::parse("java -jar fabric-server-launch.jar").unwrap()],
             tokens: Tokens::default(),
             status: Status::default(),
             triggers: {
@@ -54,6 +194,9 @@ impl Default for Config {
                 triggers
             },
             min_restart_interval_seconds: default_min_restart_interval(),
+            restart: Restart::default(),
+            shutdown: Shutdown::default(),
+            crash_loop: CrashLoop::default(),
         }
     }
 }
@@ -62,6 +205,42 @@ fn default_min_restart_interval() -> u64 {
     240
 }
 
+fn default_max_restarts() -> u32 {
+    10
+}
+
+fn default_restart_window() -> u64 {
+    60 * 60
+}
+
+fn default_max_backoff() -> u64 {
+    60 * 60
+}
+
+fn default_shutdown_warnings() -> Vec<u64> {
+    vec![5 * 60, 60, 10]
+}
+
+fn default_warning_command() -> String {
+    "say Server restarting in {time}!".to_owned()
+}
+
+fn default_stop_command() -> String {
+    "stop".to_owned()
+}
+
+fn default_grace_period() -> u64 {
+    60
+}
+
+fn default_fast_exit() -> u64 {
+    60
+}
+
+fn default_max_fast_failures() -> u32 {
+    3
+}
+
 pub async fn load<P, T>(path: P) -> T
 where
     P: AsRef<Path>,
@@ -69,7 +248,9 @@ where
 {
     let path = path.as_ref();
     if path.exists() {
-        read_config(path).await.expect("failed to read config")
+        read_config(path)
+            .await
+            .unwrap_or_else(|err| panic!("failed to read config {}: {}", path.display(), err))
     } else {
         let config = T::default();
         write_config(path, &config)
@@ -80,7 +261,7 @@ where
 }
 
 async fn write_config<T: Serialize>(path: &Path, config: &T) -> io::Result<()> {
-    let string = toml::to_string(config).expect("malformed config");
+    let string = to_toml(config).expect("malformed config");
 
     let mut file = File::create(path).await?;
     file.write_all(string.as_bytes()).await?;
@@ -88,11 +269,62 @@ async fn write_config<T: Serialize>(path: &Path, config: &T) -> io::Result<()> {
     Ok(())
 }
 
+/// Serializes through a TOML value, which emits plain values before tables regardless of the order fields are declared in,
+/// such as when a task in `run` is written as a table.
+fn to_toml<T: Serialize>(config: &T) -> Result<String, toml::ser::Error> {
+    let value = toml::Value::try_from(config)?;
+    toml::to_string(&value)
+}
+
 async fn read_config<T: DeserializeOwned>(path: &Path) -> io::Result<T> {
     let mut file = File::open(path).await?;
 
     let mut string = String::new();
     file.read_to_string(&mut string).await?;
 
-    Ok(toml::from_str::<T>(&string).expect("malformed config"))
+    toml::from_str::<T>(&string).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(config: &Config) -> Config {
+        toml::from_str(&to_toml(config).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn default_config_round_trips() {
+        let config = round_trip(&Config::default());
+        assert_eq!(config.run[0].to_string(), "java -jar fabric-server-launch.jar");
+        assert_eq!(
+            config.min_restart_interval_seconds,
+            default_min_restart_interval()
+        );
+        assert!(matches!(config.triggers.get("startup"), Some(Trigger::Startup)));
+    }
+
+    #[test]
+    fn table_tasks_round_trip() {
+        let mut prepare = Task::parse("./prepare.sh").unwrap();
+        prepare.cwd = Some("scripts".into());
+        prepare.allow_failure = true;
+        let mut server = Task::parse("java -jar server.jar").unwrap();
+        server.env.insert("JAVA_HOME".to_owned(), "/opt/java".to_owned());
+
+        let config = round_trip(&Config {
+            run: vec![prepare, server],
+            min_restart_interval_seconds: 30,
+            ..Config::default()
+        });
+
+        assert_eq!(config.run[0].cwd, Some("scripts".into()));
+        assert!(config.run[0].allow_failure);
+        assert_eq!(config.run[1].to_string(), "java -jar server.jar");
+        assert_eq!(
+            config.run[1].env.get("JAVA_HOME").map(String::as_str),
+            Some("/opt/java")
+        );
+        assert_eq!(config.min_restart_interval_seconds, 30);
+    }
 }
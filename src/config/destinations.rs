@@ -28,24 +28,19 @@ pub struct SourceSet {
     pub sources: HashMap<String, Source>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(untagged)]
 pub enum Transform {
+    #[default]
     Direct,
     Unzip { unzip: Vec<Pattern> },
 }
 
-impl Default for Transform {
-    fn default() -> Self {
-        Transform::Direct
-    }
-}
-
 impl Transform {
     pub async fn apply(&self, file: source::File) -> io::Result<Option<source::File>> {
         match self {
             Transform::Direct => Ok(Some(file)),
-            Transform::Unzip { unzip } => transform::unzip(file, &unzip).await,
+            Transform::Unzip { unzip } => transform::unzip(file, unzip).await,
         }
     }
 }
@@ -64,7 +59,7 @@ mod transform {
         file: source::File,
         patterns: &[Pattern],
     ) -> io::Result<Option<source::File>> {
-        let patterns: Vec<Pattern> = patterns.iter().cloned().collect();
+        let patterns: Vec<Pattern> = patterns.to_vec();
 
         tokio::task::spawn_blocking(move || {
             let cursor = io::Cursor::new(file.bytes.as_ref());
@@ -108,6 +103,13 @@ pub struct Pattern {
     pub exclude: bool,
 }
 
+impl Pattern {
+    #[inline]
+    pub fn matches(&self, path: &str) -> bool {
+        self.glob.matches(path) != self.exclude
+    }
+}
+
 impl Serialize for Pattern {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         if self.exclude {
@@ -137,15 +139,103 @@ impl<'de> Deserialize<'de> for Pattern {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum Source {
+    GitHubRelease {
+        github: String,
+        release: String,
+        asset: Option<Pattern>,
+        #[serde(default = "Default::default")]
+        prereleases: bool,
+    },
     GitHubArtifacts {
         github: String,
         workflow: Option<String>,
         branch: Option<String>,
         artifact: Option<String>,
+        event: Option<String>,
+        #[serde(default = "default_require_success")]
+        require_success: bool,
+        #[serde(alias = "sha")]
+        commit: Option<String>,
+    },
+    GitLab {
+        gitlab: String,
+        #[serde(default = "default_gitlab_instance")]
+        instance: String,
+        branch: Option<String>,
+        job: Option<String>,
+    },
+    Gitea {
+        #[serde(alias = "forgejo")]
+        gitea: String,
+        #[serde(default = "default_gitea_instance")]
+        instance: String,
+        branch: Option<String>,
+        artifact: Option<String>,
+    },
+    Jenkins {
+        jenkins: String,
+        #[serde(default = "default_jenkins_build")]
+        build: String,
+        artifact: Option<Pattern>,
     },
     Modrinth {
         project_id: String,
         game_version: Option<String>,
+        #[serde(default = "Default::default")]
+        loaders: Vec<String>,
+        version: Option<String>,
+        channel: Option<Channel>,
+        #[serde(default = "Default::default")]
+        dependencies: bool,
+    },
+    CurseForge {
+        #[serde(rename = "curseforge")]
+        project_id: u64,
+        file_id: Option<u64>,
+        game_version: Option<String>,
+        loader: Option<Loader>,
+    },
+    Fabric {
+        #[serde(rename = "fabric")]
+        game_version: String,
+        #[serde(default = "default_latest")]
+        loader: String,
+        #[serde(default = "default_latest")]
+        installer: String,
+    },
+    Vanilla {
+        #[serde(rename = "minecraft")]
+        version: String,
+    },
+    Paper {
+        #[serde(rename = "paper")]
+        version: String,
+        build: Option<u32>,
+    },
+    Maven {
+        maven: String,
+        artifact: String,
+        classifier: Option<String>,
+    },
+    S3 {
+        s3: String,
+        endpoint: Option<String>,
+        #[serde(default = "default_s3_region")]
+        region: String,
+    },
+    Git {
+        git: String,
+        #[serde(rename = "ref", default = "default_git_ref")]
+        reference: String,
+        subdir: Option<String>,
+        #[serde(default = "Default::default")]
+        zip: bool,
+    },
+    Mrpack {
+        mrpack: String,
+        game_version: Option<String>,
+        version: Option<String>,
+        channel: Option<Channel>,
     },
     Url {
         url: String,
@@ -155,6 +245,58 @@ pub enum Source {
     },
 }
 
+/// The least stable Modrinth release channel that should be loaded from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Channel {
+    #[serde(rename = "alpha")]
+    Alpha,
+    #[serde(rename = "beta")]
+    Beta,
+    #[serde(rename = "release")]
+    Release,
+}
+
+/// A mod loader that CurseForge files can be filtered by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Loader {
+    #[serde(rename = "forge")]
+    Forge,
+    #[serde(rename = "fabric")]
+    Fabric,
+    #[serde(rename = "quilt")]
+    Quilt,
+    #[serde(rename = "neoforge")]
+    NeoForge,
+}
+
+fn default_require_success() -> bool {
+    true
+}
+
+fn default_latest() -> String {
+    "latest".to_owned()
+}
+
+fn default_git_ref() -> String {
+    "HEAD".to_owned()
+}
+
+fn default_jenkins_build() -> String {
+    "lastSuccessfulBuild".to_owned()
+}
+
+fn default_s3_region() -> String {
+    "us-east-1".to_owned()
+}
+
+fn default_gitlab_instance() -> String {
+    "https://gitlab.com".to_owned()
+}
+
+fn default_gitea_instance() -> String {
+    "https://codeberg.org".to_owned()
+}
+
 impl Default for Destinations {
     fn default() -> Self {
         let mut destinations = HashMap::new();
@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use serde::de::{self, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A task to run, declared either as a command line following POSIX shell quoting rules
+/// (e.g. `java -Dlog4j.configurationFile="my log.xml" -jar server.jar`) or as a structured table.
+#[derive(Debug, Clone)]
+pub struct Task {
+    pub program: String,
+    pub args: Vec<String>,
+    pub env: HashMap<String, String>,
+    pub cwd: Option<PathBuf>,
+    /// Whether the following tasks should still run if this task fails.
+    pub allow_failure: bool,
+    /// The command line this task was parsed from, if it was declared as one.
+    line: Option<String>,
+}
+
+impl Task {
+    pub fn parse(line: &str) -> Result<Task, String> {
+        let words =
+            shlex::split(line).ok_or_else(|| format!("malformed quoting in task '{}'", line))?;
+
+        let mut words = words.into_iter();
+        let program = words
+            .next()
+            .ok_or_else(|| "task must not be empty".to_owned())?;
+        if program.is_empty() {
+            return Err("task program must not be empty".to_owned());
+        }
+
+        Ok(Task {
+            program,
+            args: words.collect(),
+            env: HashMap::new(),
+            cwd: None,
+            allow_failure: false,
+            line: Some(line.to_owned()),
+        })
+    }
+}
+
+impl fmt::Display for Task {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.line {
+            Some(line) => f.write_str(line),
+            None => {
+                let words = std::iter::once(&self.program).chain(&self.args);
+                let line =
+                    shlex::try_join(words.map(|word| word.as_str())).map_err(|_| fmt::Error)?;
+                f.write_str(&line)
+            }
+        }
+    }
+}
+
+/// The table form of a task, which declares either a `command` line or a `program` with `args`.
+#[derive(Serialize, Deserialize)]
+struct TaskTable {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    command: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    program: Option<String>,
+    #[serde(default = "Default::default", skip_serializing_if = "Vec::is_empty")]
+    args: Vec<String>,
+    #[serde(
+        default = "Default::default",
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    env: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cwd: Option<PathBuf>,
+    #[serde(default = "Default::default")]
+    allow_failure: bool,
+}
+
+impl TaskTable {
+    fn into_task(self) -> Result<Task, String> {
+        let mut task = match (self.command, self.program) {
+            (Some(command), None) => {
+                if !self.args.is_empty() {
+                    return Err("task must not declare both command and args".to_owned());
+                }
+                Task::parse(&command)?
+            }
+            (None, Some(program)) => {
+                if program.is_empty() {
+                    return Err("task program must not be empty".to_owned());
+                }
+                Task {
+                    program,
+                    args: self.args,
+                    env: HashMap::new(),
+                    cwd: None,
+                    allow_failure: false,
+                    line: None,
+                }
+            }
+            (Some(_), Some(_)) => {
+                return Err("task must not declare both command and program".to_owned())
+            }
+            (None, None) => return Err("task must declare either command or program".to_owned()),
+        };
+
+        task.env = self.env;
+        task.cwd = self.cwd;
+        task.allow_failure = self.allow_failure;
+
+        Ok(task)
+    }
+}
+
+impl Serialize for Task {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let simple = self.env.is_empty() && self.cwd.is_none() && !self.allow_failure;
+        match &self.line {
+            Some(line) if simple => serializer.serialize_str(line),
+            _ => {
+                let (command, program, args) = match &self.line {
+                    Some(line) => (Some(line.clone()), None, Vec::new()),
+                    None => (None, Some(self.program.clone()), self.args.clone()),
+                };
+
+                TaskTable {
+                    command,
+                    program,
+                    args,
+                    env: self.env.clone(),
+                    cwd: self.cwd.clone(),
+                    allow_failure: self.allow_failure,
+                }
+                .serialize(serializer)
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Task {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(TaskVisitor)
+    }
+}
+
+struct TaskVisitor;
+
+impl<'de> Visitor<'de> for TaskVisitor {
+    type Value = Task;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a command line or a table with a command or program")
+    }
+
+    fn visit_str<E: de::Error>(self, line: &str) -> Result<Task, E> {
+        Task::parse(line).map_err(E::custom)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Task, A::Error> {
+        let table = TaskTable::deserialize(de::value::MapAccessDeserializer::new(map))?;
+        table.into_task().map_err(de::Error::custom)
+    }
+}
+
+/// Deserializes the tasks to run, of which there must be at least one: the last task is the server itself.
+pub fn deserialize_tasks<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Task>, D::Error> {
+    let tasks = Vec::<Task>::deserialize(deserializer)?;
+    let server = tasks
+        .last()
+        .ok_or_else(|| de::Error::custom("run must declare at least one task"))?;
+
+    // a failing server has crashed, and needs to be handled by the restart policy
+    if server.allow_failure {
+        return Err(de::Error::custom(
+            "the last task is the server and must not allow failure",
+        ));
+    }
+
+    Ok(tasks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Tasks {
+        #[serde(deserialize_with = "deserialize_tasks")]
+        run: Vec<Task>,
+    }
+
+    fn parse(toml: &str) -> Result<Vec<Task>, toml::de::Error> {
+        toml::from_str::<Tasks>(toml).map(|tasks| tasks.run)
+    }
+
+    fn words(task: &Task) -> Vec<&str> {
+        std::iter::once(&task.program)
+            .chain(&task.args)
+            .map(|word| word.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn command_lines_follow_shell_quoting() {
+        let task = Task::parse(r#"java -Dlog4j.configurationFile="my log.xml" -jar 'server.jar'"#)
+            .unwrap();
+        assert_eq!(
+            words(&task),
+            [
+                "java",
+                "-Dlog4j.configurationFile=my log.xml",
+                "-jar",
+                "server.jar"
+            ]
+        );
+
+        let task = Task::parse(r#"echo a\ b "c \"d\"""#).unwrap();
+        assert_eq!(words(&task), ["echo", "a b", r#"c "d""#]);
+
+        // the original line is kept to display the task
+        assert_eq!(task.to_string(), r#"echo a\ b "c \"d\"""#);
+    }
+
+    #[test]
+    fn malformed_command_lines_are_rejected() {
+        assert!(Task::parse(r#"java -jar "server.jar"#).is_err());
+        assert!(Task::parse("").is_err());
+        assert!(Task::parse("   ").is_err());
+        assert!(Task::parse("'' -jar server.jar").is_err());
+    }
+
+    #[test]
+    fn tables_declare_a_command_or_program() {
+        let tasks = parse(
+            r#"
+            run = [
+                { command = "./prepare.sh --flag 'a b'", cwd = "scripts" },
+                { program = "java", args = ["-jar", "server.jar"], env = { KEY = "value" } },
+                "java -jar server.jar",
+            ]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(words(&tasks[0]), ["./prepare.sh", "--flag", "a b"]);
+        assert_eq!(tasks[0].cwd, Some(PathBuf::from("scripts")));
+        assert!(tasks[0].env.is_empty());
+
+        assert_eq!(words(&tasks[1]), ["java", "-jar", "server.jar"]);
+        assert_eq!(tasks[1].env.get("KEY").map(String::as_str), Some("value"));
+        assert_eq!(tasks[1].cwd, None);
+        assert_eq!(tasks[1].to_string(), "java -jar server.jar");
+
+        assert_eq!(words(&tasks[2]), ["java", "-jar", "server.jar"]);
+    }
+
+    #[test]
+    fn malformed_tables_are_rejected() {
+        assert!(parse(r#"run = [{ command = "java", program = "java" }]"#).is_err());
+        assert!(parse(r#"run = [{ command = "java", args = ["-jar"] }]"#).is_err());
+        assert!(parse(r#"run = [{ cwd = "server" }]"#).is_err());
+        assert!(parse(r#"run = [{ program = "" }]"#).is_err());
+        assert!(parse(r#"run = [{ command = "''" }]"#).is_err());
+        assert!(parse(r#"run = [{ command = "java \"server.jar" }]"#).is_err());
+    }
+
+    #[test]
+    fn run_must_not_be_empty() {
+        assert!(parse("run = []").is_err());
+        assert!(parse(r#"run = [""]"#).is_err());
+    }
+
+    #[test]
+    fn only_tasks_before_the_server_allow_failure() {
+        let tasks = parse(
+            r#"run = [{ command = "./prepare.sh", allow_failure = true }, "java -jar server.jar"]"#,
+        )
+        .unwrap();
+        assert!(tasks[0].allow_failure);
+        assert!(!tasks[1].allow_failure);
+
+        assert!(
+            parse(r#"run = [{ command = "java -jar server.jar", allow_failure = true }]"#).is_err()
+        );
+    }
+
+    #[test]
+    fn tasks_serialize_to_their_declared_form() {
+        #[derive(Serialize)]
+        struct Run {
+            run: Vec<Task>,
+        }
+        let round_trip = |toml: &str| {
+            let run = parse(toml).unwrap();
+            parse(&toml::to_string(&Run { run }).unwrap()).unwrap()
+        };
+
+        let run = round_trip(r#"run = ["java -jar 'server.jar'"]"#);
+        assert_eq!(run[0].to_string(), "java -jar 'server.jar'");
+
+        let run = round_trip(r#"run = [{ program = "java", cwd = "server" }]"#);
+        assert_eq!(words(&run[0]), ["java"]);
+        assert_eq!(run[0].cwd, Some(PathBuf::from("server")));
+    }
+}
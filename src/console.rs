@@ -0,0 +1,135 @@
+use std::io::BufRead;
+
+use tokio::sync::mpsc;
+
+/// Console lines starting with this prefix are handled by the wrapper instead of being sent to the server.
+const PREFIX: &str = "!wrapper";
+
+pub enum Input {
+    Line(String),
+    Command(Command),
+}
+
+pub enum Command {
+    /// Restarts the server, warning players unless `now` is set.
+    Restart { now: bool },
+    /// Stops the server and shuts down the wrapper.
+    Stop,
+    /// Refreshes the given destinations (or all if empty), staging them for the next restart.
+    Update { destinations: Vec<String> },
+    Status,
+}
+
+/// Reads lines from the wrapper's stdin, parsing any wrapper commands and passing everything else through.
+pub fn spawn() -> mpsc::UnboundedReceiver<Input> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    // reading stdin blocks, and the runtime would wait for a blocking task on exit, so use a plain thread
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => {
+                    eprintln!("failed to read from stdin: {:?}", err);
+                    return;
+                }
+            };
+
+            let input = match parse(&line) {
+                Some(Ok(command)) => Input::Command(command),
+                Some(Err(err)) => {
+                    eprintln!("{}", err);
+                    print_help();
+                    continue;
+                }
+                None => Input::Line(line),
+            };
+
+            if sender.send(input).is_err() {
+                return;
+            }
+        }
+    });
+
+    receiver
+}
+
+fn parse(line: &str) -> Option<Result<Command, String>> {
+    let mut words = line.split_ascii_whitespace();
+    if words.next() != Some(PREFIX) {
+        return None;
+    }
+
+    let command = match words.next() {
+        Some("restart") => match words.next() {
+            None => Ok(Command::Restart { now: false }),
+            Some("now") => Ok(Command::Restart { now: true }),
+            Some(argument) => Err(format!("unexpected argument to restart: '{}'", argument)),
+        },
+        Some("stop") => Ok(Command::Stop),
+        Some("update") => Ok(Command::Update {
+            destinations: words.map(|destination| destination.to_owned()).collect(),
+        }),
+        Some("status") => Ok(Command::Status),
+        Some(command) => Err(format!("unknown wrapper command: '{}'", command)),
+        None => Err("missing wrapper command".to_owned()),
+    };
+
+    Some(command)
+}
+
+fn print_help() {
+    println!("available wrapper commands:");
+    println!("  {} restart [now]          restart the server, warning players unless 'now' is given", PREFIX);
+    println!("  {} stop                   stop the server and shut down the wrapper", PREFIX);
+    println!("  {} update [destinations]  refresh destinations (or all) and apply them on the next restart", PREFIX);
+    println!("  {} status                 print the status of the wrapper", PREFIX);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_without_prefix_are_passed_through() {
+        assert!(parse("say hello").is_none());
+        assert!(parse("").is_none());
+        assert!(parse("!wrapperrestart").is_none());
+        assert!(parse("say !wrapper stop").is_none());
+    }
+
+    #[test]
+    fn wrapper_commands_are_parsed() {
+        assert!(matches!(
+            parse("!wrapper restart"),
+            Some(Ok(Command::Restart { now: false }))
+        ));
+        assert!(matches!(
+            parse("  !wrapper   restart now "),
+            Some(Ok(Command::Restart { now: true }))
+        ));
+        assert!(matches!(parse("!wrapper stop"), Some(Ok(Command::Stop))));
+        assert!(matches!(
+            parse("!wrapper status"),
+            Some(Ok(Command::Status))
+        ));
+
+        match parse("!wrapper update mods config") {
+            Some(Ok(Command::Update { destinations })) => {
+                assert_eq!(destinations, ["mods", "config"])
+            }
+            _ => panic!("expected an update command"),
+        }
+        assert!(matches!(
+            parse("!wrapper update"),
+            Some(Ok(Command::Update { destinations })) if destinations.is_empty()
+        ));
+    }
+
+    #[test]
+    fn malformed_wrapper_commands_are_rejected() {
+        assert!(matches!(parse("!wrapper"), Some(Err(_))));
+        assert!(matches!(parse("!wrapper reload"), Some(Err(_))));
+        assert!(matches!(parse("!wrapper restart later"), Some(Err(_))));
+    }
+}
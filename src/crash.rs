@@ -0,0 +1,312 @@
+use std::io::{self, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::status;
+
+const LOG_TAIL_LINES: usize = 20;
+const LOG_TAIL_BYTES: u64 = 64 * 1024;
+
+const FILE_TIME_TOLERANCE: Duration = Duration::from_secs(1);
+
+/// Discord limits embed descriptions to 4096 characters.
+const MAX_DESCRIPTION_LENGTH: usize = 4096;
+
+/// Information collected from the server directory after the server crashed.
+pub struct Report {
+    pub crash_report: Option<(String, String)>,
+    pub exception: Option<String>,
+    pub log_tail: Option<String>,
+}
+
+impl Report {
+    /// Collects the newest crash report written since the server was started, along with the end of the latest log.
+    pub async fn collect(server_root: &Path, started_at: SystemTime) -> Report {
+        let crash_report = match read_newest_crash_report(server_root, started_at).await {
+            Ok(crash_report) => crash_report,
+            Err(err) => {
+                eprintln!("failed to read crash report: {:?}", err);
+                None
+            }
+        };
+
+        let log_tail = match read_log_tail(server_root).await {
+            Ok(log_tail) => log_tail,
+            Err(err) => {
+                eprintln!("failed to read latest log: {:?}", err);
+                None
+            }
+        };
+
+        let exception = crash_report
+            .as_ref()
+            .and_then(|(_, contents)| find_crash_report_exception(contents))
+            .or_else(|| log_tail.as_deref().and_then(find_log_exception));
+
+        Report {
+            crash_report,
+            exception,
+            log_tail,
+        }
+    }
+
+    pub fn into_status(self, title: String) -> (status::Payload, Option<status::Attachment>) {
+        let mut description = String::new();
+        if let Some(exception) = &self.exception {
+            description.push_str(&format!("```\n{}\n```\n", exception));
+        }
+
+        if let Some(log_tail) = &self.log_tail {
+            let header = "Latest log:\n```\n";
+            let footer = "\n```";
+
+            // keep the end of the log, since that is where the crash will be
+            let available = MAX_DESCRIPTION_LENGTH
+                .saturating_sub(description.chars().count() + header.len() + footer.len());
+            let skip = log_tail.chars().count().saturating_sub(available);
+            let log_tail: String = log_tail.chars().skip(skip).collect();
+
+            description.push_str(header);
+            description.push_str(&log_tail.replace("```", "'''"));
+            description.push_str(footer);
+        }
+
+        let mut payload = status::Payload::new_sanitized(String::new());
+        payload.embeds.push(status::Embed {
+            title: Some(title),
+            ty: status::EmbedType::Rich,
+            description: Some(description).filter(|description| !description.is_empty()),
+            url: None,
+            color: Some(0xFF0000),
+        });
+
+        let attachment = self
+            .crash_report
+            .map(|(file_name, contents)| status::Attachment {
+                file_name,
+                bytes: contents.into_bytes(),
+            });
+
+        (payload, attachment)
+    }
+}
+
+async fn read_newest_crash_report(
+    server_root: &Path,
+    started_at: SystemTime,
+) -> io::Result<Option<(String, String)>> {
+    let crash_reports = server_root.join("crash-reports");
+    if !crash_reports.exists() {
+        return Ok(None);
+    }
+
+    let mut newest: Option<(SystemTime, PathBuf)> = None;
+
+    let mut entries = fs::read_dir(&crash_reports).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        if !metadata.is_file() {
+            continue;
+        }
+
+        // ignore crash reports left over from previous crashes, allowing for coarse file timestamps
+        let modified = metadata.modified()?;
+        if modified + FILE_TIME_TOLERANCE < started_at {
+            continue;
+        }
+
+        if newest
+            .as_ref()
+            .map(|(time, _)| modified > *time)
+            .unwrap_or(true)
+        {
+            newest = Some((modified, entry.path()));
+        }
+    }
+
+    match newest {
+        Some((_, path)) => {
+            let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+            let contents = fs::read(&path).await?;
+            Ok(Some((
+                file_name,
+                String::from_utf8_lossy(&contents).into_owned(),
+            )))
+        }
+        None => Ok(None),
+    }
+}
+
+async fn read_log_tail(server_root: &Path) -> io::Result<Option<String>> {
+    let path = server_root.join("logs").join("latest.log");
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let mut file = fs::File::open(&path).await?;
+    let length = file.metadata().await?.len();
+    file.seek(SeekFrom::Start(length.saturating_sub(LOG_TAIL_BYTES)))
+        .await?;
+
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).await?;
+
+    let log = String::from_utf8_lossy(&bytes);
+    let lines: Vec<&str> = log.lines().collect();
+    let tail = lines[lines.len().saturating_sub(LOG_TAIL_LINES)..].join("\n");
+
+    Ok(Some(tail))
+}
+
+/// Finds the exception in a Minecraft crash report, which follows the description after an empty line.
+fn find_crash_report_exception(crash_report: &str) -> Option<String> {
+    let mut lines = crash_report
+        .lines()
+        .skip_while(|line| !line.starts_with("Description:"))
+        .skip(1);
+
+    lines
+        .find(|line| !line.trim().is_empty())
+        .map(|line| line.trim().to_owned())
+}
+
+fn find_log_exception(log: &str) -> Option<String> {
+    log.lines()
+        .rev()
+        .find(|line| line.contains("Exception") || line.contains("Error:"))
+        .map(|line| line.trim().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixture::TempDir;
+
+    /// A server directory with the `crash-reports` and `logs` directories created.
+    fn server_root(name: &str) -> TempDir {
+        let root = TempDir::new(&format!("crash-{}", name));
+        std::fs::create_dir_all(root.path().join("crash-reports")).unwrap();
+        std::fs::create_dir_all(root.path().join("logs")).unwrap();
+        root
+    }
+
+    fn write(root: &TempDir, path: &str, contents: &str, modified: SystemTime) {
+        let path = root.path().join(path);
+        std::fs::write(&path, contents).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    const CRASH_REPORT: &str = "---- Minecraft Crash Report ----\n\
+        // Who set us up the TNT?\n\
+        \n\
+        Time: 2024-01-01 12:00:00\n\
+        Description: Exception in server tick loop\n\
+        \n\
+        java.lang.IllegalStateException: Lock is no longer valid\n\
+        \tat net.minecraft.server.MinecraftServer.tick(MinecraftServer.java:100)\n";
+
+    #[tokio::test]
+    async fn newest_crash_report_since_start_is_collected() {
+        let root = server_root("newest");
+        let started_at = SystemTime::now() - Duration::from_secs(60);
+
+        write(
+            &root,
+            "crash-reports/crash-old.txt",
+            "Description: Old\n\nOldException",
+            started_at - Duration::from_secs(60),
+        );
+        write(
+            &root,
+            "crash-reports/crash-first.txt",
+            "Description: First\n\nFirstException",
+            started_at + Duration::from_secs(10),
+        );
+        write(
+            &root,
+            "crash-reports/crash-second.txt",
+            CRASH_REPORT,
+            started_at + Duration::from_secs(20),
+        );
+
+        let report = Report::collect(root.path(), started_at).await;
+        let (file_name, contents) = report.crash_report.unwrap();
+        assert_eq!(file_name, "crash-second.txt");
+        assert_eq!(contents, CRASH_REPORT);
+        assert_eq!(
+            report.exception.as_deref(),
+            Some("java.lang.IllegalStateException: Lock is no longer valid")
+        );
+    }
+
+    #[tokio::test]
+    async fn crash_reports_from_before_start_are_ignored() {
+        let root = server_root("old");
+        let started_at = SystemTime::now();
+
+        write(
+            &root,
+            "crash-reports/crash-old.txt",
+            CRASH_REPORT,
+            started_at - Duration::from_secs(60),
+        );
+
+        let report = Report::collect(root.path(), started_at).await;
+        assert!(report.crash_report.is_none());
+        assert!(report.exception.is_none());
+        assert!(report.log_tail.is_none());
+    }
+
+    #[tokio::test]
+    async fn log_tail_keeps_last_lines() {
+        let root = server_root("log");
+        let started_at = SystemTime::now();
+
+        let mut log: Vec<String> = (0..30)
+            .map(|line| format!("[INFO] line {}", line))
+            .collect();
+        log.insert(25, "java.lang.NullPointerException: oops".to_owned());
+        write(&root, "logs/latest.log", &log.join("\n"), started_at);
+
+        let report = Report::collect(root.path(), started_at).await;
+        let log_tail = report.log_tail.unwrap();
+        let lines: Vec<&str> = log_tail.lines().collect();
+        assert_eq!(lines.len(), LOG_TAIL_LINES);
+        assert_eq!(lines[0], "[INFO] line 11");
+        assert_eq!(lines[LOG_TAIL_LINES - 1], "[INFO] line 29");
+
+        // without a crash report, the exception is taken from the log
+        assert_eq!(
+            report.exception.as_deref(),
+            Some("java.lang.NullPointerException: oops")
+        );
+    }
+
+    #[test]
+    fn exceptions_are_found_in_crash_reports() {
+        assert_eq!(
+            find_crash_report_exception(CRASH_REPORT).as_deref(),
+            Some("java.lang.IllegalStateException: Lock is no longer valid")
+        );
+        assert_eq!(find_crash_report_exception("no description"), None);
+    }
+
+    #[test]
+    fn last_exception_is_found_in_logs() {
+        let log = "java.lang.RuntimeException: first\n\
+            [INFO] Stopping server\n  java.lang.Error: second  \n[INFO] Done";
+        assert_eq!(
+            find_log_exception(log).as_deref(),
+            Some("java.lang.Error: second")
+        );
+        assert_eq!(find_log_exception("[INFO] Done"), None);
+    }
+}
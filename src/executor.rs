@@ -1,28 +1,268 @@
+use std::fmt;
+use std::future::Future;
 use std::io;
+use std::pin::Pin;
+use std::process::{ExitStatus, Stdio};
+use std::sync::Arc;
+use std::time::Duration;
 
+use tokio::io::AsyncWriteExt;
 use tokio::process;
+use tokio::sync::{mpsc, Mutex};
+
+use crate::config;
 
 pub struct Executor {
-    tasks: Vec<String>,
+    tasks: Vec<config::Task>,
+    shutdown: config::Shutdown,
+    control: mpsc::UnboundedReceiver<Control>,
+    sender: mpsc::UnboundedSender<Control>,
+}
+
+enum Control {
+    Input(String),
+    Stop { warn: bool },
+    Kill,
+}
+
+pub enum Exit {
+    /// All tasks ran to completion without any disallowed failures.
+    Success,
+    /// A task exited with a non-zero exit code.
+    Failure { task: String, code: i32 },
+    /// A task was terminated by a signal.
+    Signal { task: String, signal: i32 },
+    /// The server was stopped through a [`Handle`].
+    Stopped,
+}
+
+impl Exit {
+    fn from_status(task: &config::Task, status: ExitStatus) -> Exit {
+        if status.success() {
+            return Exit::Success;
+        }
+
+        let task = task.to_string();
+        if let Some(code) = status.code() {
+            return Exit::Failure { task, code };
+        }
+
+        #[cfg(unix)]
+        if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+            return Exit::Signal { task, signal };
+        }
+
+        Exit::Failure { task, code: -1 }
+    }
+
+    pub fn is_success(&self) -> bool {
+        matches!(self, Exit::Success)
+    }
+}
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Exit::Success => write!(f, "all tasks exited successfully"),
+            Exit::Failure { task, code } => write!(f, "`{}` exited with code {}", task, code),
+            Exit::Signal { task, signal } => match signal_name(*signal) {
+                Some(name) => write!(f, "`{}` was terminated by {}", task, name),
+                None => write!(f, "`{}` was terminated by signal {}", task, signal),
+            },
+            Exit::Stopped => write!(f, "the server was stopped"),
+        }
+    }
+}
+
+fn signal_name(signal: i32) -> Option<&'static str> {
+    match signal {
+        1 => Some("SIGHUP"),
+        2 => Some("SIGINT"),
+        6 => Some("SIGABRT"),
+        9 => Some("SIGKILL"),
+        11 => Some("SIGSEGV"),
+        15 => Some("SIGTERM"),
+        _ => None,
+    }
+}
+
+#[derive(Clone)]
+pub struct Handle {
+    sender: mpsc::UnboundedSender<Control>,
+}
+
+impl Handle {
+    /// Sends the given line to the server's console.
+    pub fn input(&self, line: String) {
+        let _ = self.sender.send(Control::Input(line));
+    }
+
+    /// Requests the server to be stopped gracefully through its console, skipping any tasks that follow it.
+    /// If `warn` is set, players are warned ahead of time according to the shutdown configuration.
+    pub fn stop(&self, warn: bool) {
+        let _ = self.sender.send(Control::Stop { warn });
+    }
+
+    /// Requests the server to be killed immediately.
+    pub fn kill(&self) {
+        let _ = self.sender.send(Control::Kill);
+    }
+}
+
+type Stdin = Arc<Mutex<Option<process::ChildStdin>>>;
+
+struct Shutdown {
+    sequence: Pin<Box<dyn Future<Output = ()> + Send>>,
+    warn: bool,
 }
 
 impl Executor {
-    pub fn new(tasks: Vec<String>) -> Executor {
-        Executor { tasks }
+    pub fn new(tasks: Vec<config::Task>, shutdown: config::Shutdown) -> Executor {
+        let (sender, control) = mpsc::unbounded_channel();
+        Executor {
+            tasks,
+            shutdown,
+            control,
+            sender,
+        }
+    }
+
+    pub fn handle(&self) -> Handle {
+        Handle {
+            sender: self.sender.clone(),
+        }
     }
 
-    pub async fn run(&mut self) -> io::Result<()> {
-        for task in &self.tasks {
+    pub async fn run(&mut self) -> io::Result<Exit> {
+        for (index, task) in self.tasks.iter().enumerate() {
             println!("executing: '{}'", task);
 
-            let task: Vec<&str> = task.split_ascii_whitespace().collect();
+            // the last task is the server itself: only it is stopped through its console
+            let server = index == self.tasks.len() - 1;
+
+            let mut command = process::Command::new(&task.program);
+            command.args(&task.args).envs(&task.env);
+            if let Some(cwd) = &task.cwd {
+                command.current_dir(cwd);
+            }
+
+            if server {
+                command.stdin(Stdio::piped());
+
+                // keep the server out of our process group so that it does not receive a terminal's
+                // interrupt directly: we want to stop it through its console instead
+                #[cfg(unix)]
+                command.process_group(0);
+            }
+
+            let mut child = command.spawn()?;
+            let stdin: Stdin = Arc::new(Mutex::new(child.stdin.take()));
+
+            let mut shutdown: Option<Shutdown> = None;
+
+            let status = loop {
+                tokio::select! {
+                    status = child.wait() => break status?,
+                    Some(control) = self.control.recv() => match control {
+                        Control::Input(line) => {
+                            if server {
+                                send_command(&stdin, &line).await;
+                            } else {
+                                eprintln!("server is not running yet, ignoring input");
+                            }
+                        }
+                        Control::Stop { warn } if server => {
+                            // a stop without warnings takes over from one that is still warning players
+                            let replace = match &shutdown {
+                                Some(shutdown) => shutdown.warn && !warn,
+                                None => true,
+                            };
+                            if replace {
+                                println!("stopping server...");
+                                let sequence =
+                                    shutdown_sequence(stdin.clone(), self.shutdown.clone(), warn);
+                                shutdown = Some(Shutdown {
+                                    sequence: Box::pin(sequence),
+                                    warn,
+                                });
+                            }
+                        }
+                        Control::Stop { .. } | Control::Kill => {
+                            println!("killing server...");
+                            child.kill().await?;
+                            return Ok(Exit::Stopped);
+                        }
+                    },
+                    _ = async { shutdown.as_mut().unwrap().sequence.as_mut().await }, if shutdown.is_some() => {
+                        eprintln!("server did not stop within the grace period! killing...");
+                        child.kill().await?;
+                        return Ok(Exit::Stopped);
+                    }
+                }
+            };
+
+            if shutdown.is_some() {
+                return Ok(Exit::Stopped);
+            }
 
-            let mut command = process::Command::new(task[0]);
-            command.args(&task[1..]);
+            let exit = Exit::from_status(task, status);
+            if !exit.is_success() {
+                if task.allow_failure && !server {
+                    eprintln!("{}, continuing", exit);
+                } else {
+                    return Ok(exit);
+                }
+            }
+        }
+
+        Ok(Exit::Success)
+    }
+}
 
-            command.spawn()?.wait().await?;
+/// Warns players and sends the stop command to the server, completing once the grace period has passed.
+async fn shutdown_sequence(stdin: Stdin, shutdown: config::Shutdown, warn: bool) {
+    if warn {
+        let mut warnings = shutdown.warnings.clone();
+        warnings.sort_unstable_by(|a, b| b.cmp(a));
+        warnings.dedup();
+
+        for (index, &seconds) in warnings.iter().enumerate() {
+            let command = shutdown
+                .warning_command
+                .replace("{time}", &format_time(seconds));
+            send_command(&stdin, &command).await;
+
+            let next = warnings.get(index + 1).copied().unwrap_or(0);
+            tokio::time::sleep(Duration::from_secs(seconds - next)).await;
         }
+    }
+
+    send_command(&stdin, &shutdown.stop_command).await;
+
+    tokio::time::sleep(Duration::from_secs(shutdown.grace_period_seconds)).await;
+}
+
+async fn send_command(stdin: &Stdin, command: &str) {
+    let mut stdin = stdin.lock().await;
+    if let Some(writer) = stdin.as_mut() {
+        let line = format!("{}\n", command);
+        if let Err(err) = writer.write_all(line.as_bytes()).await {
+            eprintln!("failed to send '{}' to server: {:?}", command, err);
+            *stdin = None;
+        }
+    }
+}
+
+fn format_time(seconds: u64) -> String {
+    let (amount, unit) = if seconds >= 60 && seconds.is_multiple_of(60) {
+        (seconds / 60, "minute")
+    } else {
+        (seconds, "second")
+    };
 
-        Ok(())
+    if amount == 1 {
+        format!("{} {}", amount, unit)
+    } else {
+        format!("{} {}s", amount, unit)
     }
 }
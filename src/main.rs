@@ -1,10 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::io;
 use std::path::{Path, PathBuf};
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 
 use futures::FutureExt;
 use tokio::fs;
+use tokio::sync::mpsc;
 
 pub use config::Config;
 use executor::Executor;
@@ -12,34 +13,38 @@ use status::StatusWriter;
 
 mod cache;
 mod config;
+mod console;
+mod crash;
 mod executor;
+mod restart;
+mod signal;
 mod source;
 mod status;
+mod trigger;
 
-const CACHE_ROOT: &str = "wrapper_cache";
+#[cfg(test)]
+mod test_fixture;
+#[cfg(test)]
+mod test_server;
 
-// TODO: implement triggers
+const CACHE_ROOT: &str = "wrapper_cache";
 
 #[derive(Clone)]
 pub struct Context {
     pub github: source::github::Client,
     pub modrinth: source::modrinth::Client,
+    pub curseforge: source::curseforge::Client,
+    pub gitlab: source::gitlab::Client,
+    pub gitea: source::gitea::Client,
+    pub s3: source::s3::Client,
+    pub git: source::git::Client,
     pub client: reqwest::Client,
     pub status: StatusWriter,
 }
 
-#[tokio::main]
-pub async fn main() {
-    let config_path = std::env::args().nth(1).unwrap_or_else(|| "config.toml".to_owned());
-    let destinations_path = std::env::args().nth(2).unwrap_or_else(|| "destinations.toml".to_owned());
-
-    // loop { - disabled due to pterodactyl
-        let config: Config = config::load(&config_path).await;
-        let destinations: config::Destinations = config::load(&destinations_path).await;
-
-        let min_restart_interval = Duration::from_secs(config.min_restart_interval_seconds);
-
-        let status = match config.status.webhook {
+impl Context {
+    pub fn new(config: &Config) -> Context {
+        let status = match &config.status.webhook {
             Some(webhook) => StatusWriter::from(status::webhook::Client::open(webhook)),
             None => StatusWriter::none(),
         };
@@ -51,24 +56,84 @@ pub async fn main() {
             .unwrap();
         let github = source::github::Client::new(config.tokens.github.clone());
         let modrinth = source::modrinth::Client::new(client.clone());
-        let ctx = Context {
+        let curseforge =
+            source::curseforge::Client::new(client.clone(), config.tokens.curseforge.clone());
+        let gitlab = source::gitlab::Client::new(config.tokens.gitlab.clone());
+        let gitea = source::gitea::Client::new(config.tokens.gitea.clone());
+        let s3 = source::s3::Client::new(client.clone(), config.tokens.s3.clone());
+        let git = source::git::Client::new(Path::new(CACHE_ROOT).join(".repositories"));
+        Context {
             github,
             modrinth,
+            curseforge,
+            gitlab,
+            gitea,
+            s3,
+            git,
             client,
             status,
-        };
+        }
+    }
+}
+
+#[tokio::main]
+pub async fn main() {
+    let config_path = std::env::args().nth(1).unwrap_or_else(|| "config.toml".to_owned());
+    let destinations_path = std::env::args().nth(2).unwrap_or_else(|| "destinations.toml".to_owned());
+
+    // triggers are only bound once, so changing them requires restarting the wrapper
+    let config: Config = config::load(&config_path).await;
+    let mut triggers = trigger::spawn(&config, &destinations_path);
 
-        let destinations: Vec<PreparedDestination> =
-            prepare_destinations(&ctx, destinations.destinations).await;
+    let mut signals = signal::Signals::new().expect("failed to listen for signals");
+    let mut console = console::spawn();
 
-        let changed_sources: Vec<_> = destinations
+    let mut restarts = restart::Restarts::new();
+    let mut staged: Vec<PreparedDestination> = Vec::new();
+
+    // destinations are prepared in the background one request at a time, with the results sent back once ready
+    let (prepared_sender, mut prepared_receiver) = mpsc::unbounded_channel();
+    let mut queued: VecDeque<Preparation> = VecDeque::new();
+    let mut preparing = false;
+
+    // sources that changed since the server last ran successfully, as (destination, key, token) triples
+    let mut unverified: Vec<(String, String, cache::Token)> = Vec::new();
+    let mut fast_failures = 0;
+
+    loop {
+        let config: Config = config::load(&config_path).await;
+        let destinations: config::Destinations = config::load(&destinations_path).await;
+
+        let min_restart_interval = Duration::from_secs(config.min_restart_interval_seconds);
+
+        let ctx = Context::new(&config);
+
+        // files staged by triggers while the server was running are applied before anything else
+        for destination in staged.drain(..) {
+            unverified.extend(destination.changed_sources());
+            destination
+                .apply()
+                .await
+                .expect("failed to apply destination");
+        }
+
+        let startup_triggers: Vec<&str> = config
+            .triggers
             .iter()
-            .flat_map(|destination| destination.cache_files.iter())
-            .filter(|(_, source)| source.changed())
-            .map(|(name, _)| name.to_owned())
+            .filter(|(_, trigger)| matches!(trigger, config::Trigger::Startup))
+            .map(|(name, _)| name.as_str())
             .collect();
 
-        for destination in destinations {
+        let prepared: Vec<PreparedDestination> = prepare_destinations(
+            &ctx,
+            select_destinations(&destinations, &startup_triggers),
+        )
+        .await;
+
+        let changed_sources = changed_sources(&prepared);
+
+        for destination in prepared {
+            unverified.extend(destination.changed_sources());
             destination
                 .apply()
                 .await
@@ -76,26 +141,7 @@ pub async fn main() {
         }
 
         let payload = if !changed_sources.is_empty() {
-            let mut payload = status::Payload::new_sanitized(String::new());
-
-            let description = format!(
-                "Here's what changed:\n{}",
-                changed_sources
-                    .into_iter()
-                    .map(|source| format!("- `{}`", source))
-                    .collect::<Vec<_>>()
-                    .join("\n")
-            );
-
-            payload.embeds.push(status::Embed {
-                title: Some("Server starting up...".to_owned()),
-                ty: status::EmbedType::Rich,
-                description: Some(description),
-                url: None,
-                color: Some(0x00FF00),
-            });
-
-            payload
+            changes_payload("Server starting up...".to_owned(), changed_sources)
         } else {
             status::Payload::from("Starting up server...")
         };
@@ -103,29 +149,404 @@ pub async fn main() {
         ctx.status.write(payload);
 
         let start = Instant::now();
+        let started_at = SystemTime::now();
+
+        let mut executor = Executor::new(config.run.clone(), config.shutdown.clone());
+        let handle = executor.handle();
+
+        let run = executor.run();
+        tokio::pin!(run);
+
+        // once the server has run for long enough, the versions it runs are known to work: confirm them right away
+        // (or once the current preparation is done), so that updates staged while it keeps running keep them as the
+        // previous version instead of replacing them
+        let fast_exit_timeout =
+            tokio::time::sleep(Duration::from_secs(config.crash_loop.fast_exit_seconds));
+        tokio::pin!(fast_exit_timeout);
+
+        let mut shutting_down = false;
+
+        let result = loop {
+            tokio::select! {
+                result = &mut run => break result,
+                _ = &mut fast_exit_timeout, if !preparing && !unverified.is_empty() => (),
+                Some(event) = triggers.recv() => queued.push_back(Preparation::Trigger(event)),
+                Some(Prepared { preparation, result }) = prepared_receiver.recv() => {
+                    preparing = false;
+
+                    let prepared = result.unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+                    let changed = prepared
+                        .iter()
+                        .any(|destination| destination.changed_sources().next().is_some());
+                    staged.extend(prepared);
+
+                    // there is no point in kicking players off for a restart that changes nothing
+                    if let Preparation::Trigger(event) = preparation {
+                        if event.action == config::TriggerAction::Restart && changed {
+                            handle.stop(true);
+                        }
+                    }
+                }
+                Some(input) = console.recv() => match input {
+                    console::Input::Line(line) => handle.input(line),
+                    console::Input::Command(console::Command::Restart { now }) => {
+                        ctx.status.write("Server restart requested from the console...");
+                        handle.stop(!now);
+                    }
+                    console::Input::Command(console::Command::Stop) => {
+                        shutting_down = true;
+                        handle.stop(false);
+                    }
+                    console::Input::Command(console::Command::Update { destinations: names }) => {
+                        queued.push_back(Preparation::Update(names));
+                    }
+                    console::Input::Command(console::Command::Status) => {
+                        print_status(Instant::now() - start, &staged);
+                    }
+                },
+                _ = signals.recv() => {
+                    if shutting_down {
+                        handle.kill();
+                    } else {
+                        // hosting panels only wait a limited time for us to stop, so skip the warnings
+                        shutting_down = true;
+                        handle.stop(false);
+                    }
+                }
+            }
+
+            // the cache must not be written to by two loaders at once, so confirming has to wait for any preparation
+            if !preparing {
+                if fast_exit_timeout.is_elapsed() && !unverified.is_empty() {
+                    confirm(&destinations, &unverified).await;
+                    unverified.clear();
+                }
+
+                if let Some(preparation) = queued.pop_front() {
+                    spawn_preparation(&ctx, &destinations, preparation, &prepared_sender);
+                    preparing = true;
+                }
+            }
+        };
+
+        // files must not be applied while they are still being prepared, so let all requested preparations finish
+        while preparing {
+            let Prepared { result, .. } = prepared_receiver
+                .recv()
+                .await
+                .expect("preparation channel closed");
+            staged.extend(result.unwrap_or_else(|panic| std::panic::resume_unwind(panic)));
+
+            preparing = match queued.pop_front() {
+                Some(preparation) => {
+                    spawn_preparation(&ctx, &destinations, preparation, &prepared_sender);
+                    true
+                }
+                None => false,
+            };
+        }
+
+        if shutting_down {
+            println!("server stopped, shutting down...");
+            ctx.status.send("Server stopped! Shutting down...").await;
+            break;
+        }
+
+        let uptime = Instant::now() - start;
+        let fast_exit = uptime < Duration::from_secs(config.crash_loop.fast_exit_seconds);
+
+        let (success, closed) = match result {
+            Ok(executor::Exit::Stopped) => {
+                if !fast_exit {
+                    confirm(&destinations, &unverified).await;
+                    unverified.clear();
+                    fast_failures = 0;
+                }
+
+                println!("server stopped, restarting...");
+                continue;
+            }
+            Ok(executor::Exit::Success) => {
+                println!("server closed");
+                (true, "Server closed!".to_owned())
+            }
+            Ok(exit) => {
+                eprintln!("server closed: {}", exit);
+
+                let server_root = config
+                    .run
+                    .last()
+                    .and_then(|task| task.cwd.clone())
+                    .unwrap_or_else(|| PathBuf::from("."));
+                let report = crash::Report::collect(&server_root, started_at).await;
+                if let Some(exception) = &report.exception {
+                    eprintln!("caused by: {}", exception);
+                }
 
-        let mut executor = Executor::new(config.run);
-        if let Err(err) = executor.run().await {
-            eprintln!("server exited with error: {:?}", err);
+                let (payload, attachment) =
+                    report.into_status(format!("Server crashed: {}!", exit));
+                match attachment {
+                    Some(attachment) => ctx.status.write_with_attachment(payload, attachment),
+                    None => ctx.status.write(payload),
+                }
+
+                (false, format!("Server closed: {}!", exit))
+            }
+            Err(err) => {
+                eprintln!("server exited with error: {:?}", err);
+                (false, format!("Server failed to run: {}!", err))
+            }
+        };
+
+        if success || !fast_exit {
+            confirm(&destinations, &unverified).await;
+            unverified.clear();
+            fast_failures = 0;
         } else {
-            println!("server closed");
+            fast_failures += 1;
+
+            let max_failures = config.crash_loop.max_failures;
+            if max_failures != 0 && fast_failures >= max_failures {
+                println!("server is crash looping! rolling back...");
+                fast_failures = 0;
+
+                match rollback(&destinations, &unverified).await {
+                    Ok(reverted) if !reverted.is_empty() => {
+                        ctx.status.write(format!(
+                            "Server crashed {} times in a row! Rolled back: {}",
+                            max_failures,
+                            reverted
+                                .iter()
+                                .map(|source| format!("`{}`", source))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ));
+                        restarts.reset_backoff();
+                    }
+                    Ok(_) => {
+                        ctx.status.write(format!(
+                            "Server crashed {} times in a row, but there is nothing to roll back!",
+                            max_failures
+                        ));
+                    }
+                    Err(err) => eprintln!("failed to roll back: {:?}", err),
+                }
+
+                unverified.clear();
+            }
+        }
+
+        match restarts.next(&config.restart, min_restart_interval, uptime, success) {
+            restart::Decision::Restart { delay } if delay.is_zero() => {
+                ctx.status.write(format!("{} Restarting...", closed));
+            }
+            restart::Decision::Restart { delay } => {
+                println!("server restarted very quickly! waiting a bit...");
+
+                ctx.status.write(format!(
+                    "{} Server restarted too quickly! Waiting for {} seconds...",
+                    closed,
+                    delay.as_secs()
+                ));
+
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => (),
+                    _ = signals.recv() => break,
+                }
+            }
+            restart::Decision::Exit { reason } => {
+                println!("not restarting: {}", reason);
+                ctx.status
+                    .send(format!("{} Not restarting: {}", closed, reason))
+                    .await;
+                break;
+            }
+        }
+    }
+
+    // the server is no longer running, so any files that are still staged can be applied safely
+    for destination in staged {
+        destination
+            .apply()
+            .await
+            .expect("failed to apply destination");
+    }
+}
+
+/// A refresh of destinations that was requested while the server is running.
+enum Preparation {
+    Trigger(trigger::Event),
+    Update(Vec<String>),
+}
+
+/// The destinations that were prepared in the background for a request, or the panic that interrupted it.
+struct Prepared {
+    preparation: Preparation,
+    result: std::thread::Result<Vec<PreparedDestination>>,
+}
+
+/// Prepares the destinations for the given request in the background, so that signals and console input are still
+/// handled while sources are downloading. The result is sent back once it is ready.
+fn spawn_preparation(
+    ctx: &Context,
+    destinations: &config::Destinations,
+    preparation: Preparation,
+    sender: &mpsc::UnboundedSender<Prepared>,
+) {
+    let ctx = ctx.clone();
+    let destinations = destinations.clone();
+    let sender = sender.clone();
+
+    tokio::spawn(async move {
+        let prepared = async {
+            match &preparation {
+                Preparation::Trigger(event) => handle_trigger(&ctx, &destinations, event).await,
+                Preparation::Update(names) => update_destinations(&ctx, &destinations, names).await,
+            }
+        };
+        let result = std::panic::AssertUnwindSafe(prepared).catch_unwind().await;
+
+        let _ = sender.send(Prepared {
+            preparation,
+            result,
+        });
+    });
+}
+
+async fn handle_trigger(
+    ctx: &Context,
+    destinations: &config::Destinations,
+    event: &trigger::Event,
+) -> Vec<PreparedDestination> {
+    println!("refreshing destinations for trigger '{}'", event.trigger);
+
+    let prepared = prepare_destinations(
+        ctx,
+        select_destinations(destinations, &[event.trigger.as_str()]),
+    )
+    .await;
+
+    let changed_sources = changed_sources(&prepared);
+    let title = match event.action {
+        _ if changed_sources.is_empty() => format!("Trigger `{}` fired!", event.trigger),
+        config::TriggerAction::Restart => {
+            format!("Trigger `{}` fired! Restarting server...", event.trigger)
         }
+        config::TriggerAction::Stage => format!(
+            "Trigger `{}` fired! Changes will be applied on the next restart.",
+            event.trigger
+        ),
+    };
+    ctx.status.write(changes_payload(title, changed_sources));
+
+    prepared
+}
 
-        // let interval = Instant::now() - start;
-        // if interval < min_restart_interval {
-        //     println!("server restarted very quickly! waiting a bit...");
+/// Refreshes the destinations with the given names (or all if empty) to be applied on the next restart.
+async fn update_destinations(
+    ctx: &Context,
+    destinations: &config::Destinations,
+    names: &[String],
+) -> Vec<PreparedDestination> {
+    let selected: HashMap<String, config::Destination> = destinations
+        .destinations
+        .iter()
+        .filter(|(name, _)| names.is_empty() || names.contains(name))
+        .map(|(name, destination)| (name.clone(), destination.clone()))
+        .collect();
+
+    for name in names {
+        if !selected.contains_key(name) {
+            eprintln!("unknown destination '{}'", name);
+        }
+    }
+
+    let prepared = prepare_destinations(ctx, selected).await;
 
-        //     let delay = min_restart_interval - interval;
-        //     ctx.status.write(format!(
-        //         "Server restarted too quickly! Waiting for {} seconds...",
-        //         delay.as_secs()
-        //     ));
+    let changed_sources = changed_sources(&prepared);
+    if changed_sources.is_empty() {
+        println!("nothing changed");
+    } else {
+        println!(
+            "updated {}: changes will be applied on the next restart",
+            changed_sources.join(", ")
+        );
+    }
 
-        //     tokio::time::sleep(delay.into()).await;
-        // } else {
-        //     ctx.status.write("Server closed! Restarting...");
-        // }
-    // }
+    prepared
+}
+
+fn print_status(uptime: Duration, staged: &[PreparedDestination]) {
+    println!("server has been running for {} seconds", uptime.as_secs());
+
+    let changed_sources = changed_sources(staged);
+    if changed_sources.is_empty() {
+        println!("no changes are staged");
+    } else {
+        let destinations: Vec<&str> = staged
+            .iter()
+            .map(|destination| destination.name.as_str())
+            .collect();
+        println!(
+            "staged changes in {}: {}",
+            destinations.join(", "),
+            changed_sources.join(", ")
+        );
+    }
+}
+
+/// Selects all destinations that are refreshed by any of the given triggers.
+fn select_destinations(
+    destinations: &config::Destinations,
+    triggers: &[&str],
+) -> HashMap<String, config::Destination> {
+    destinations
+        .destinations
+        .iter()
+        .filter(|(_, destination)| {
+            destination
+                .triggers
+                .iter()
+                .any(|trigger| triggers.contains(&trigger.as_str()))
+        })
+        .map(|(name, destination)| (name.clone(), destination.clone()))
+        .collect()
+}
+
+fn changed_sources(destinations: &[PreparedDestination]) -> Vec<String> {
+    destinations
+        .iter()
+        .flat_map(|destination| destination.cache_files.iter())
+        .filter(|(_, source)| source.changed())
+        .map(|(name, _)| name.to_owned())
+        .collect()
+}
+
+fn changes_payload(title: String, changed_sources: Vec<String>) -> status::Payload {
+    let mut payload = status::Payload::new_sanitized(String::new());
+
+    let description = if !changed_sources.is_empty() {
+        format!(
+            "Here's what changed:\n{}",
+            changed_sources
+                .into_iter()
+                .map(|source| format!("- `{}`", source))
+                .collect::<Vec<_>>()
+                .join("\n")
+        )
+    } else {
+        "Nothing changed.".to_owned()
+    };
+
+    payload.embeds.push(status::Embed {
+        title: Some(title),
+        ty: status::EmbedType::Rich,
+        description: Some(description),
+        url: None,
+        color: Some(0x00FF00),
+    });
+
+    payload
 }
 
 async fn prepare_destinations(
@@ -139,10 +560,12 @@ async fn prepare_destinations(
         let future = tokio::spawn(async move {
             prepare_destination(&ctx, &destination_name, &destination)
                 .await
-                .expect(&format!(
-                    "failed to prepare destination '{}'",
-                    destination_name
-                ))
+                .unwrap_or_else(|err| {
+                    panic!(
+                        "failed to prepare destination '{}': {:?}",
+                        destination_name, err
+                    )
+                })
         });
         futures.push(future.map(|result| result.unwrap()));
     }
@@ -162,11 +585,10 @@ async fn prepare_destination(
 
     let mut cache = cache::Loader::open(&cache_root).await?;
 
-    for (_, source_set) in &destination.sources {
+    for source_set in destination.sources.values() {
         for (key, source) in &source_set.sources {
-            let cache_entry = cache.entry(key.clone());
-            match source::load(ctx, cache_entry, source, &source_set.transform).await {
-                Ok(reference) => cache_files.push((key.clone(), reference)),
+            match source::load(ctx, &mut cache, key, source, &source_set.transform).await {
+                Ok(references) => cache_files.extend(references),
                 Err(err) => {
                     eprintln!("failed to load {}: {:?}! excluding.", key, err);
                     ctx.status
@@ -176,22 +598,180 @@ async fn prepare_destination(
         }
     }
 
+    match source::resolve_dependencies(ctx, destination).await {
+        Ok(dependencies) => {
+            if !dependencies.problems.is_empty() {
+                for problem in &dependencies.problems {
+                    eprintln!("dependency problem in {}: {}", destination_name, problem);
+                }
+                ctx.status.write(format!(
+                    "Found dependency problems in {}:\n{}",
+                    destination_name,
+                    dependencies
+                        .problems
+                        .iter()
+                        .map(|problem| format!("- {}", problem))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                ));
+            }
+
+            for dependency in &dependencies.resolved {
+                let cache_entry = cache.entry(dependency.key.clone());
+                match source::modrinth::load_dependency(&ctx.modrinth, cache_entry, dependency)
+                    .await
+                {
+                    Ok(reference) => cache_files.push((dependency.key.clone(), reference)),
+                    Err(err) => {
+                        eprintln!(
+                            "failed to load dependency {}: {:?}! excluding.",
+                            dependency.key, err
+                        );
+                        ctx.status.write(format!(
+                            "Failed to load dependency {}... Excluding!",
+                            dependency.key
+                        ));
+                    }
+                }
+            }
+        }
+        Err(err) => {
+            eprintln!(
+                "failed to resolve dependencies of {}: {:?}",
+                destination_name, err
+            );
+            ctx.status.write(format!(
+                "Failed to resolve dependencies of {}!",
+                destination_name
+            ));
+        }
+    }
+
     let old_files = cache.close().await?;
 
     Ok(PreparedDestination {
+        name: destination_name.to_owned(),
         root: destination.path.clone(),
         cache_files,
         old_files,
     })
 }
 
+/// Returns the keys of all sources in the given destination that are outdated along with the tokens of their newer
+/// versions, without downloading anything.
+async fn check_destination(
+    ctx: &Context,
+    destination_name: &str,
+    destination: &config::Destination,
+) -> Result<Vec<(String, cache::Token)>> {
+    let cache_root = Path::new(CACHE_ROOT).join(destination_name);
+    let cache = cache::Loader::open(&cache_root).await?;
+
+    let mut outdated = Vec::new();
+
+    for source_set in destination.sources.values() {
+        for (key, source) in &source_set.sources {
+            match source::resolve(ctx, source).await {
+                Ok(Some(token)) => {
+                    if !cache.is_current(key, &token) {
+                        outdated.push((key.clone(), token));
+                    }
+                }
+                Ok(None) => (),
+                Err(err) => eprintln!("failed to resolve {}: {:?}", key, err),
+            }
+        }
+    }
+
+    Ok(outdated)
+}
+
+/// Marks the given (destination, key, token) sources as working, so that they are restored when a later version is
+/// rolled back. Sources that have been updated to another version since are left alone.
+async fn confirm(destinations: &config::Destinations, sources: &[(String, String, cache::Token)]) {
+    for destination_name in destinations.destinations.keys() {
+        let versions: Vec<(&String, &cache::Token)> = sources
+            .iter()
+            .filter(|(name, _, _)| name == destination_name)
+            .map(|(_, key, token)| (key, token))
+            .collect();
+        if versions.is_empty() {
+            continue;
+        }
+
+        let cache_root = Path::new(CACHE_ROOT).join(destination_name);
+        let result = async {
+            let mut cache = cache::Loader::open(&cache_root).await?;
+            for (key, token) in versions {
+                cache.confirm(key, token);
+            }
+            cache.save().await
+        };
+
+        if let Err(err) = result.await {
+            eprintln!("failed to confirm sources of {}: {:?}", destination_name, err);
+        }
+    }
+}
+
+/// Restores the previous version of the given (destination, key, token) sources, returning the keys that were reverted.
+async fn rollback(
+    destinations: &config::Destinations,
+    sources: &[(String, String, cache::Token)],
+) -> Result<Vec<String>> {
+    let mut reverted = Vec::new();
+
+    for (destination_name, destination) in &destinations.destinations {
+        let keys: Vec<&String> = sources
+            .iter()
+            .filter(|(name, _, _)| name == destination_name)
+            .map(|(_, key, _)| key)
+            .collect();
+        if keys.is_empty() {
+            continue;
+        }
+
+        let cache_root = Path::new(CACHE_ROOT).join(destination_name);
+        let mut cache = cache::Loader::open(&cache_root).await?;
+
+        let mut rolled_back = PreparedDestination {
+            name: destination_name.clone(),
+            root: destination.path.clone(),
+            cache_files: Vec::new(),
+            old_files: Vec::new(),
+        };
+
+        for key in keys {
+            if let Some((removed, restored)) = cache.rollback(key).await? {
+                println!("rolled back {}", key);
+                rolled_back.old_files.push(removed);
+                rolled_back.cache_files.push((key.clone(), restored));
+                reverted.push(key.clone());
+            }
+        }
+
+        cache.save().await?;
+        rolled_back.apply().await?;
+    }
+
+    Ok(reverted)
+}
+
 struct PreparedDestination {
+    name: String,
     root: PathBuf,
     cache_files: Vec<(String, cache::Reference)>,
     old_files: Vec<cache::Reference>,
 }
 
 impl PreparedDestination {
+    fn changed_sources(&self) -> impl Iterator<Item = (String, String, cache::Token)> + '_ {
+        self.cache_files
+            .iter()
+            .filter(|(_, reference)| reference.changed())
+            .map(|(key, reference)| (self.name.clone(), key.clone(), reference.token().clone()))
+    }
+
     async fn apply(&self) -> Result<()> {
         if self.root.exists() {
             for reference in &self.old_files {
@@ -221,6 +801,26 @@ pub enum Error {
     Reqwest(#[from] reqwest::Error),
     #[error("malformed github reference")]
     MalformedGitHubReference(String),
+    #[error("malformed gitea reference")]
+    MalformedGiteaReference(String),
+    #[error("malformed maven reference")]
+    MalformedMavenReference(String),
+    #[error("malformed maven metadata")]
+    MalformedMavenMetadata(String),
+    #[error("checksum mismatch")]
+    ChecksumMismatch(String),
+    #[error("malformed s3 reference")]
+    MalformedS3Reference(String),
+    #[error("malformed s3 response")]
+    MalformedS3Response(String),
+    #[error("malformed modpack")]
+    MalformedModpack(String),
+    #[error("missing curseforge api key")]
+    MissingCurseForgeKey,
+    #[error("distribution disabled")]
+    DistributionDisabled(String),
+    #[error("git error")]
+    Git(String),
     #[error("missing artifact")]
     MissingArtifact,
 }
@@ -0,0 +1,231 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::config::{self, RestartPolicy};
+
+pub enum Decision {
+    Restart { delay: Duration },
+    Exit { reason: String },
+}
+
+/// Tracks recent server restarts in order to apply the configured restart policy and backoff.
+pub struct Restarts {
+    history: VecDeque<Instant>,
+    backoff: Option<Duration>,
+}
+
+impl Restarts {
+    pub fn new() -> Restarts {
+        Restarts {
+            history: VecDeque::new(),
+            backoff: None,
+        }
+    }
+
+    pub fn next(
+        &mut self,
+        config: &config::Restart,
+        min_interval: Duration,
+        uptime: Duration,
+        success: bool,
+    ) -> Decision {
+        self.next_at(Instant::now(), config, min_interval, uptime, success)
+    }
+
+    fn next_at(
+        &mut self,
+        now: Instant,
+        config: &config::Restart,
+        min_interval: Duration,
+        uptime: Duration,
+        success: bool,
+    ) -> Decision {
+        match config.policy {
+            RestartPolicy::Never => {
+                return Decision::Exit {
+                    reason: "restart policy is set to never".to_owned(),
+                }
+            }
+            RestartPolicy::OnFailure if success => {
+                return Decision::Exit {
+                    reason: "server exited successfully".to_owned(),
+                }
+            }
+            _ => (),
+        }
+
+        let window = Duration::from_secs(config.window_seconds);
+        while let Some(&restart) = self.history.front() {
+            if now.duration_since(restart) > window {
+                self.history.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        if config.max_restarts != 0 && self.history.len() >= config.max_restarts as usize {
+            return Decision::Exit {
+                reason: format!(
+                    "server restarted {} times within {} seconds",
+                    self.history.len(),
+                    config.window_seconds
+                ),
+            };
+        }
+
+        let delay = if uptime < min_interval {
+            let max_backoff = Duration::from_secs(config.max_backoff_seconds);
+            let backoff = match self.backoff {
+                Some(backoff) => (backoff * 2).min(max_backoff),
+                None => min_interval.min(max_backoff),
+            };
+            self.backoff = Some(backoff);
+            backoff
+        } else {
+            self.backoff = None;
+            Duration::ZERO
+        };
+
+        self.history.push_back(now + delay);
+
+        Decision::Restart { delay }
+    }
+
+    /// Forgets about previous quick restarts, such as after the cause of a crash loop has been removed.
+    pub fn reset_backoff(&mut self) {
+        self.backoff = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIN_INTERVAL: Duration = Duration::from_secs(10);
+
+    fn restart(policy: RestartPolicy, max_restarts: u32) -> config::Restart {
+        config::Restart {
+            policy,
+            max_restarts,
+            window_seconds: 60,
+            max_backoff_seconds: 40,
+        }
+    }
+
+    fn delay(decision: Decision) -> Duration {
+        match decision {
+            Decision::Restart { delay } => delay,
+            Decision::Exit { reason } => panic!("unexpected exit: {}", reason),
+        }
+    }
+
+    #[test]
+    fn policy_decides_whether_to_restart() {
+        let mut restarts = Restarts::new();
+        let now = Instant::now();
+        let uptime = Duration::from_secs(100);
+
+        let never = restart(RestartPolicy::Never, 0);
+        assert!(matches!(
+            restarts.next_at(now, &never, MIN_INTERVAL, uptime, false),
+            Decision::Exit { .. }
+        ));
+
+        let on_failure = restart(RestartPolicy::OnFailure, 0);
+        assert!(matches!(
+            restarts.next_at(now, &on_failure, MIN_INTERVAL, uptime, true),
+            Decision::Exit { .. }
+        ));
+        assert_eq!(
+            delay(restarts.next_at(now, &on_failure, MIN_INTERVAL, uptime, false)),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn quick_restarts_back_off_exponentially() {
+        let config = restart(RestartPolicy::Always, 0);
+        let mut restarts = Restarts::new();
+        let now = Instant::now();
+        let quick = Duration::from_secs(1);
+
+        let delays: Vec<Duration> = (0..4)
+            .map(|_| delay(restarts.next_at(now, &config, MIN_INTERVAL, quick, false)))
+            .collect();
+        let expected: Vec<Duration> = [10, 20, 40, 40].map(Duration::from_secs).to_vec();
+        assert_eq!(delays, expected);
+
+        // a server that stays up for long enough resets the backoff
+        let long = Duration::from_secs(100);
+        assert_eq!(
+            delay(restarts.next_at(now, &config, MIN_INTERVAL, long, false)),
+            Duration::ZERO
+        );
+        assert_eq!(
+            delay(restarts.next_at(now, &config, MIN_INTERVAL, quick, false)),
+            MIN_INTERVAL
+        );
+    }
+
+    #[test]
+    fn reset_backoff_forgets_quick_restarts() {
+        let config = restart(RestartPolicy::Always, 0);
+        let mut restarts = Restarts::new();
+        let now = Instant::now();
+        let quick = Duration::from_secs(1);
+
+        restarts.next_at(now, &config, MIN_INTERVAL, quick, false);
+        restarts.next_at(now, &config, MIN_INTERVAL, quick, false);
+        restarts.reset_backoff();
+
+        assert_eq!(
+            delay(restarts.next_at(now, &config, MIN_INTERVAL, quick, false)),
+            MIN_INTERVAL
+        );
+    }
+
+    #[test]
+    fn too_many_restarts_within_window_exit() {
+        let config = restart(RestartPolicy::Always, 2);
+        let mut restarts = Restarts::new();
+        let now = Instant::now();
+        let uptime = Duration::from_secs(100);
+
+        delay(restarts.next_at(now, &config, MIN_INTERVAL, uptime, false));
+        delay(restarts.next_at(now, &config, MIN_INTERVAL, uptime, false));
+        assert!(matches!(
+            restarts.next_at(now, &config, MIN_INTERVAL, uptime, false),
+            Decision::Exit { .. }
+        ));
+
+        // restarts older than the window are forgotten
+        let later = now + Duration::from_secs(61);
+        assert_eq!(
+            delay(restarts.next_at(later, &config, MIN_INTERVAL, uptime, false)),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn delayed_restarts_count_from_when_they_happen() {
+        let config = restart(RestartPolicy::Always, 1);
+        let mut restarts = Restarts::new();
+        let now = Instant::now();
+
+        // the restart is delayed by 40 seconds, so it is still within the window 90 seconds from now
+        restarts.backoff = Some(Duration::from_secs(40));
+        delay(restarts.next_at(now, &config, MIN_INTERVAL, Duration::ZERO, false));
+
+        let later = now + Duration::from_secs(90);
+        assert!(matches!(
+            restarts.next_at(
+                later,
+                &config,
+                MIN_INTERVAL,
+                Duration::from_secs(100),
+                false
+            ),
+            Decision::Exit { .. }
+        ));
+    }
+}
@@ -0,0 +1,47 @@
+use std::io;
+
+#[cfg(unix)]
+use tokio::signal::unix::{signal, Signal, SignalKind};
+
+/// Listens for the signals that ask the wrapper to shut down, such as those sent by Pterodactyl or systemd.
+#[cfg(unix)]
+pub struct Signals {
+    terminate: Signal,
+    interrupt: Signal,
+}
+
+#[cfg(unix)]
+impl Signals {
+    pub fn new() -> io::Result<Signals> {
+        Ok(Signals {
+            terminate: signal(SignalKind::terminate())?,
+            interrupt: signal(SignalKind::interrupt())?,
+        })
+    }
+
+    pub async fn recv(&mut self) {
+        tokio::select! {
+            _ = self.terminate.recv() => println!("received SIGTERM"),
+            _ = self.interrupt.recv() => println!("received SIGINT"),
+        }
+    }
+}
+
+/// Listens for Ctrl-C, the only shutdown signal available outside of unix.
+#[cfg(not(unix))]
+pub struct Signals;
+
+#[cfg(not(unix))]
+impl Signals {
+    pub fn new() -> io::Result<Signals> {
+        Ok(Signals)
+    }
+
+    pub async fn recv(&mut self) {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            println!("received Ctrl-C");
+        } else {
+            std::future::pending::<()>().await;
+        }
+    }
+}
@@ -1,48 +1,434 @@
+use std::collections::HashSet;
+
 use bytes::Bytes;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 
 use crate::{Error, Result};
 use crate::cache;
 use crate::config::{self, Source};
 use crate::Context;
 
+pub mod curseforge;
+pub mod fabric;
+pub mod git;
+pub mod gitea;
 pub mod github;
+pub mod gitlab;
 pub mod http;
+pub mod jenkins;
+pub mod maven;
 pub mod modrinth;
+pub mod mrpack;
+pub mod paper;
 pub mod path;
+pub mod s3;
+pub mod vanilla;
+
+/// Loads the given source into the cache, returning the keys and references of all files that it consists of.
+pub async fn load(
+    ctx: &Context,
+    cache: &mut cache::Loader,
+    key: &str,
+    source: &config::Source,
+    transform: &config::Transform,
+) -> Result<Vec<(String, cache::Reference)>> {
+    match source {
+        Source::Mrpack {
+            mrpack,
+            game_version,
+            version,
+            channel,
+        } => {
+            let pack = mrpack::pack_source(mrpack, game_version, version, channel);
+            mrpack::load(ctx, cache, key, &pack).await
+        }
+        Source::Git {
+            git,
+            reference,
+            subdir,
+            zip,
+        } => {
+            let checkout = git::Checkout {
+                url: git.clone(),
+                reference: reference.clone(),
+                subdir: subdir.clone(),
+                zip: *zip,
+            };
+
+            git::load(&ctx.git, cache, key, checkout, transform).await
+        }
+        _ => {
+            let reference = load_file(ctx, cache.entry(key), source, transform).await?;
+            Ok(vec![(key.to_owned(), reference)])
+        }
+    }
+}
 
-pub async fn load<'a>(
+async fn load_file<'a>(
     ctx: &Context,
     cache: cache::Entry<'a>,
     source: &config::Source,
     transform: &config::Transform,
 ) -> Result<cache::Reference> {
     match source {
+        Source::GitHubRelease {
+            github,
+            release,
+            asset,
+            prereleases,
+        } => {
+            let (owner, repository) = parse_github_repository(github)?;
+            let filter = github::ReleaseFilter {
+                release: release.clone(),
+                asset: asset.clone(),
+                prereleases: *prereleases,
+            };
+
+            github::load_release(&ctx.github, cache, owner, repository, filter, transform).await
+        }
         Source::GitHubArtifacts {
             github,
             workflow,
             branch,
             artifact,
-        } => match github.split("/").collect::<Vec<&str>>().as_slice() {
-            [owner, repository] => {
-                let filter = github::Filter {
-                    workflow: workflow.clone(),
-                    branch: branch.clone(),
-                    artifact: artifact.clone(),
-                };
-
-                github::load(&ctx.github, cache, owner, repository, filter, transform).await
-            }
-            _ => Err(Error::MalformedGitHubReference(github.clone())),
-        },
+            event,
+            require_success,
+            commit,
+        } => {
+            let (owner, repository) = parse_github_repository(github)?;
+            let filter = github::Filter {
+                workflow: workflow.clone(),
+                branch: branch.clone(),
+                artifact: artifact.clone(),
+                event: event.clone(),
+                require_success: *require_success,
+                commit: commit.clone(),
+            };
+
+            github::load(&ctx.github, cache, owner, repository, filter, transform).await
+        }
+        Source::GitLab {
+            gitlab,
+            instance,
+            branch,
+            job,
+        } => {
+            let filter = gitlab::Filter {
+                instance: instance.clone(),
+                branch: branch.clone(),
+                job: job.clone(),
+            };
+
+            gitlab::load(&ctx.gitlab, cache, gitlab, filter, transform).await
+        }
+        Source::Gitea {
+            gitea,
+            instance,
+            branch,
+            artifact,
+        } => {
+            let (owner, repository) = parse_gitea_repository(gitea)?;
+            let filter = gitea::Filter {
+                instance: instance.clone(),
+                branch: branch.clone(),
+                artifact: artifact.clone(),
+            };
+
+            gitea::load(&ctx.gitea, cache, owner, repository, filter, transform).await
+        }
+        Source::Jenkins {
+            jenkins,
+            build,
+            artifact,
+        } => {
+            let filter = jenkins::Filter {
+                build: build.clone(),
+                artifact: artifact.clone(),
+            };
+
+            jenkins::load(&ctx.client, cache, jenkins, filter, transform).await
+        }
         Source::Modrinth {
             project_id,
             game_version,
-        } => modrinth::load(&ctx.modrinth, cache, project_id, game_version, transform).await,
+            loaders,
+            version,
+            channel,
+            ..
+        } => {
+            let filter = modrinth::Filter {
+                game_version: game_version.clone(),
+                loaders: loaders.clone(),
+                version: version.clone(),
+                channel: *channel,
+            };
+
+            modrinth::load(&ctx.modrinth, cache, project_id, filter, transform).await
+        }
+        Source::CurseForge {
+            project_id,
+            file_id,
+            game_version,
+            loader,
+        } => {
+            let filter = curseforge::Filter {
+                file_id: *file_id,
+                game_version: game_version.clone(),
+                loader: *loader,
+            };
+
+            curseforge::load(&ctx.curseforge, cache, *project_id, filter, transform).await
+        }
+        Source::Fabric {
+            game_version,
+            loader,
+            installer,
+        } => fabric::load(&ctx.client, cache, game_version, loader, installer, transform).await,
+        Source::Vanilla { version } => vanilla::load(&ctx.client, cache, version, transform).await,
+        Source::Paper { version, build } => {
+            paper::load(&ctx.client, cache, version, *build, transform).await
+        }
+        Source::Maven {
+            maven,
+            artifact,
+            classifier,
+        } => maven::load(&ctx.client, cache, maven, artifact, classifier, transform).await,
+        Source::S3 {
+            s3,
+            endpoint,
+            region,
+        } => {
+            let location = s3::Location::parse(s3, endpoint, region)?;
+            s3::load(&ctx.s3, cache, &location, transform).await
+        }
         Source::Url { url } => http::load(&ctx.client, cache, url, transform).await,
         Source::Path { path } => path::load(cache, path, transform).await,
+        Source::Mrpack { .. } => unreachable!("modpacks are loaded as multiple files"),
+        Source::Git { .. } => unreachable!("git repositories are loaded as multiple files"),
     }
 }
 
+/// Resolves the cache token of the latest version of the given source without downloading it.
+/// Returns `None` if the latest version cannot be determined.
+pub async fn resolve(ctx: &Context, source: &config::Source) -> Result<Option<cache::Token>> {
+    match source {
+        Source::GitHubRelease {
+            github,
+            release,
+            asset,
+            prereleases,
+        } => {
+            let (owner, repository) = parse_github_repository(github)?;
+            let filter = github::ReleaseFilter {
+                release: release.clone(),
+                asset: asset.clone(),
+                prereleases: *prereleases,
+            };
+
+            github::resolve_release(&ctx.github, owner, repository, filter).await
+        }
+        Source::GitHubArtifacts {
+            github,
+            workflow,
+            branch,
+            artifact,
+            event,
+            require_success,
+            commit,
+        } => {
+            let (owner, repository) = parse_github_repository(github)?;
+            let filter = github::Filter {
+                workflow: workflow.clone(),
+                branch: branch.clone(),
+                artifact: artifact.clone(),
+                event: event.clone(),
+                require_success: *require_success,
+                commit: commit.clone(),
+            };
+
+            github::resolve(&ctx.github, owner, repository, filter).await
+        }
+        Source::GitLab {
+            gitlab,
+            instance,
+            branch,
+            job,
+        } => {
+            let filter = gitlab::Filter {
+                instance: instance.clone(),
+                branch: branch.clone(),
+                job: job.clone(),
+            };
+
+            gitlab::resolve(&ctx.gitlab, gitlab, filter).await
+        }
+        Source::Gitea {
+            gitea,
+            instance,
+            branch,
+            artifact,
+        } => {
+            let (owner, repository) = parse_gitea_repository(gitea)?;
+            let filter = gitea::Filter {
+                instance: instance.clone(),
+                branch: branch.clone(),
+                artifact: artifact.clone(),
+            };
+
+            gitea::resolve(&ctx.gitea, owner, repository, filter).await
+        }
+        Source::Jenkins {
+            jenkins,
+            build,
+            artifact,
+        } => {
+            let filter = jenkins::Filter {
+                build: build.clone(),
+                artifact: artifact.clone(),
+            };
+
+            jenkins::resolve(&ctx.client, jenkins, filter).await
+        }
+        Source::Modrinth {
+            project_id,
+            game_version,
+            loaders,
+            version,
+            channel,
+            ..
+        } => {
+            let filter = modrinth::Filter {
+                game_version: game_version.clone(),
+                loaders: loaders.clone(),
+                version: version.clone(),
+                channel: *channel,
+            };
+
+            modrinth::resolve(&ctx.modrinth, project_id, filter).await
+        }
+        Source::CurseForge {
+            project_id,
+            file_id,
+            game_version,
+            loader,
+        } => {
+            let filter = curseforge::Filter {
+                file_id: *file_id,
+                game_version: game_version.clone(),
+                loader: *loader,
+            };
+
+            curseforge::resolve(&ctx.curseforge, *project_id, filter).await
+        }
+        Source::Fabric {
+            game_version,
+            loader,
+            installer,
+        } => fabric::resolve(&ctx.client, game_version, loader, installer).await,
+        Source::Vanilla { version } => vanilla::resolve(&ctx.client, version).await,
+        Source::Paper { version, build } => paper::resolve(&ctx.client, version, *build).await,
+        Source::Maven {
+            maven,
+            artifact,
+            classifier,
+        } => maven::resolve(&ctx.client, maven, artifact, classifier).await,
+        Source::Git { git, reference, .. } => git::resolve(&ctx.git, git, reference).await,
+        Source::S3 {
+            s3,
+            endpoint,
+            region,
+        } => {
+            let location = s3::Location::parse(s3, endpoint, region)?;
+            s3::resolve(&ctx.s3, &location).await
+        }
+        Source::Url { url } => http::resolve(&ctx.client, url).await,
+        Source::Path { path } => path::resolve(path).await,
+        Source::Mrpack {
+            mrpack,
+            game_version,
+            version,
+            channel,
+        } => {
+            // a modpack is only outdated when the pack itself has changed
+            let pack = mrpack::pack_source(mrpack, game_version, version, channel);
+            Box::pin(resolve(ctx, &pack)).await
+        }
+    }
+}
+
+/// Resolves the required dependencies of the Modrinth sources in the given destination that have them enabled.
+pub async fn resolve_dependencies(
+    ctx: &Context,
+    destination: &config::Destination,
+) -> Result<modrinth::Dependencies> {
+    let sources = destination
+        .sources
+        .values()
+        .flat_map(|source_set| source_set.sources.iter());
+
+    let keys: HashSet<String> = sources.clone().map(|(key, _)| key.clone()).collect();
+
+    let roots: Vec<modrinth::Root> = sources
+        .filter_map(|(key, source)| match source {
+            Source::Modrinth {
+                project_id,
+                game_version,
+                loaders,
+                version,
+                channel,
+                dependencies,
+            } => Some(modrinth::Root {
+                key,
+                project_id,
+                filter: modrinth::Filter {
+                    game_version: game_version.clone(),
+                    loaders: loaders.clone(),
+                    version: version.clone(),
+                    channel: *channel,
+                },
+                dependencies: *dependencies,
+            }),
+            _ => None,
+        })
+        .collect();
+
+    if !roots.iter().any(|root| root.dependencies) {
+        return Ok(modrinth::Dependencies {
+            resolved: Vec::new(),
+            problems: Vec::new(),
+        });
+    }
+
+    modrinth::resolve_dependencies(&ctx.modrinth, roots, &keys).await
+}
+
+fn parse_github_repository(github: &str) -> Result<(&str, &str)> {
+    parse_repository(github).ok_or_else(|| Error::MalformedGitHubReference(github.to_owned()))
+}
+
+fn parse_gitea_repository(gitea: &str) -> Result<(&str, &str)> {
+    parse_repository(gitea).ok_or_else(|| Error::MalformedGiteaReference(gitea.to_owned()))
+}
+
+fn parse_repository(repository: &str) -> Option<(&str, &str)> {
+    match repository.split('/').collect::<Vec<&str>>().as_slice() {
+        [owner, repository] => Some((owner, repository)),
+        _ => None,
+    }
+}
+
+/// Everything except unreserved characters is encoded in url path segments.
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Percent-encodes a single segment of a url path, such as a tag name that may itself contain `/`.
+fn encode_path_segment(segment: &str) -> String {
+    utf8_percent_encode(segment, PATH_SEGMENT).to_string()
+}
+
 pub struct File {
     pub name: String,
     pub bytes: Bytes,
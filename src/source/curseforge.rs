@@ -0,0 +1,202 @@
+use std::cmp;
+use std::sync::Arc;
+
+use chrono::DateTime;
+use chrono::Utc;
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+
+use crate::{cache, config, source, Error, Result};
+
+pub async fn load<'a>(
+    client: &Client,
+    cache: cache::Entry<'a>,
+    project_id: u64,
+    filter: Filter,
+    transform: &config::Transform,
+) -> Result<cache::Reference> {
+    let file = resolve_file(client, project_id, &filter).await?;
+    if let Some(file) = file {
+        use cache::UpdateResult::*;
+        match cache.try_update(file.token()) {
+            Mismatch(updater) => {
+                // authors can opt out of third-party downloads, in which case the file has no download url
+                let url = file.download_url.as_ref().ok_or_else(|| {
+                    Error::DistributionDisabled(format!(
+                        "{} ({}) has to be downloaded manually",
+                        file.file_name, file.display_name
+                    ))
+                })?;
+
+                let response = client.get(url).await?.error_for_status()?;
+                let bytes = response.bytes().await?;
+
+                if let Some(sha1) = file.sha1() {
+                    if !sha1.eq_ignore_ascii_case(&hex::encode(Sha1::digest(&bytes))) {
+                        return Err(Error::ChecksumMismatch(url.clone()));
+                    }
+                }
+
+                let file = source::File {
+                    name: file.file_name,
+                    bytes,
+                };
+
+                if let Some(file) = transform.apply(file).await? {
+                    Ok(updater.update(file).await?)
+                } else {
+                    Err(Error::MissingArtifact)
+                }
+            }
+            Match(reference) => Ok(reference),
+        }
+    } else {
+        cache.get_existing().ok_or(Error::MissingArtifact)
+    }
+}
+
+pub async fn resolve(
+    client: &Client,
+    project_id: u64,
+    filter: Filter,
+) -> Result<Option<cache::Token>> {
+    let file = resolve_file(client, project_id, &filter).await?;
+    Ok(file.map(|file| file.token()))
+}
+
+async fn resolve_file(client: &Client, project_id: u64, filter: &Filter) -> Result<Option<File>> {
+    if let Some(file_id) = filter.file_id {
+        return Ok(Some(client.get_file(project_id, file_id).await?));
+    }
+
+    let mut files = client.get_files(project_id, filter).await?;
+    files.retain(|file| file.is_available);
+    files.sort_by_key(|file| cmp::Reverse(file.file_date));
+
+    Ok(files.into_iter().next())
+}
+
+#[derive(Clone, Debug)]
+pub struct Filter {
+    /// A specific file to load instead of the newest file.
+    pub file_id: Option<u64>,
+    pub game_version: Option<String>,
+    pub loader: Option<config::Loader>,
+}
+
+#[derive(Clone)]
+pub struct Client {
+    client: Arc<reqwest::Client>,
+    api_key: Option<String>,
+}
+
+impl Client {
+    const BASE_URL: &'static str = "https://api.curseforge.com";
+
+    pub fn new(client: reqwest::Client, api_key: Option<String>) -> Client {
+        Client {
+            client: Arc::new(client),
+            api_key,
+        }
+    }
+
+    async fn get_files(&self, project_id: u64, filter: &Filter) -> Result<Vec<File>> {
+        let url = format!("{}/v1/mods/{}/files", Client::BASE_URL, project_id);
+
+        let mut query = vec![("pageSize", "50".to_owned())];
+        if let Some(game_version) = &filter.game_version {
+            query.push(("gameVersion", game_version.clone()));
+        }
+        if let Some(loader) = filter.loader {
+            query.push(("modLoaderType", loader_type(loader).to_string()));
+        }
+
+        let url = reqwest::Url::parse_with_params(&url, &query).expect("malformed curseforge url");
+        let response = self.get_api(url.as_str()).await?;
+        Ok(response.json::<Response<Vec<File>>>().await?.data)
+    }
+
+    async fn get_file(&self, project_id: u64, file_id: u64) -> Result<File> {
+        let url = format!(
+            "{}/v1/mods/{}/files/{}",
+            Client::BASE_URL,
+            project_id,
+            file_id
+        );
+        let response = self.get_api(&url).await?;
+        Ok(response.json::<Response<File>>().await?.data)
+    }
+
+    async fn get_api(&self, url: &str) -> Result<reqwest::Response> {
+        let api_key = self.api_key.as_ref().ok_or(Error::MissingCurseForgeKey)?;
+        let response = self
+            .client
+            .get(url)
+            .header("x-api-key", api_key)
+            .send()
+            .await?;
+        Ok(response.error_for_status()?)
+    }
+
+    #[inline]
+    pub async fn get(&self, url: &str) -> Result<reqwest::Response> {
+        Ok(self.client.get(url).send().await?)
+    }
+}
+
+fn loader_type(loader: config::Loader) -> u32 {
+    match loader {
+        config::Loader::Forge => 1,
+        config::Loader::Fabric => 4,
+        config::Loader::Quilt => 5,
+        config::Loader::NeoForge => 6,
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Response<T> {
+    data: T,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+#[allow(unused)]
+struct File {
+    id: u64,
+    display_name: String,
+    file_name: String,
+    file_date: DateTime<Utc>,
+    is_available: bool,
+    download_url: Option<String>,
+    hashes: Vec<FileHash>,
+    file_fingerprint: u32,
+}
+
+impl File {
+    fn sha1(&self) -> Option<&str> {
+        // CurseForge identifies hash algorithms by number: 1 is SHA-1 and 2 is MD5
+        self.hashes
+            .iter()
+            .find(|hash| hash.algo == 1)
+            .map(|hash| hash.value.as_str())
+    }
+
+    fn token(&self) -> cache::Token {
+        let sha1 = self.sha1().and_then(|sha1| {
+            let mut hash = [0u8; 20];
+            hex::decode_to_slice(sha1, &mut hash).ok()?;
+            Some(hash)
+        });
+
+        match sha1 {
+            Some(hash) => cache::Token::Sha1(hash),
+            None => cache::Token::Fingerprint(self.file_fingerprint),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct FileHash {
+    value: String,
+    algo: u32,
+}
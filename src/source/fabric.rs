@@ -0,0 +1,144 @@
+use serde::Deserialize;
+
+use crate::{cache, config, source, Error, Result};
+
+const BASE_URL: &str = "https://meta.fabricmc.net";
+
+const LAUNCHER_NAME: &str = "fabric-server-launch.jar";
+
+pub async fn load<'a>(
+    client: &reqwest::Client,
+    cache: cache::Entry<'a>,
+    game_version: &str,
+    loader: &str,
+    installer: &str,
+    transform: &config::Transform,
+) -> Result<cache::Reference> {
+    let versions = resolve_versions(client, game_version, loader, installer).await?;
+
+    if let Some(versions) = versions {
+        use cache::UpdateResult::*;
+        match cache.try_update(versions.token()) {
+            Mismatch(updater) => {
+                let url = format!(
+                    "{}/v2/versions/loader/{}/{}/{}/server/jar",
+                    BASE_URL, versions.game, versions.loader, versions.installer
+                );
+                println!("downloading {}...", url);
+
+                let response = client.get(&url).send().await?.error_for_status()?;
+                let bytes = response.bytes().await?;
+                let file = source::File {
+                    name: LAUNCHER_NAME.to_owned(),
+                    bytes,
+                };
+
+                if let Some(file) = transform.apply(file).await? {
+                    Ok(updater.update(file).await?)
+                } else {
+                    Err(Error::MissingArtifact)
+                }
+            }
+            Match(reference) => Ok(reference),
+        }
+    } else {
+        cache.get_existing().ok_or(Error::MissingArtifact)
+    }
+}
+
+pub async fn resolve(
+    client: &reqwest::Client,
+    game_version: &str,
+    loader: &str,
+    installer: &str,
+) -> Result<Option<cache::Token>> {
+    let versions = resolve_versions(client, game_version, loader, installer).await?;
+    Ok(versions.map(|versions| versions.token()))
+}
+
+struct Versions {
+    game: String,
+    loader: String,
+    installer: String,
+}
+
+impl Versions {
+    /// The launcher is built from these versions alone, so they identify it without downloading it.
+    fn token(&self) -> cache::Token {
+        cache::Token::Version(format!("{}/{}/{}", self.game, self.loader, self.installer))
+    }
+}
+
+/// Resolves `latest` versions to the newest stable ones, where the loader also needs to support the game version.
+async fn resolve_versions(
+    client: &reqwest::Client,
+    game_version: &str,
+    loader: &str,
+    installer: &str,
+) -> Result<Option<Versions>> {
+    let game = if game_version == "latest" {
+        let versions: Vec<Version> = get(client, "v2/versions/game").await?;
+        match latest_stable(versions) {
+            Some(version) => version,
+            None => return Ok(None),
+        }
+    } else {
+        game_version.to_owned()
+    };
+
+    let loader = if loader == "latest" {
+        let path = format!("v2/versions/loader/{}", game);
+        let versions: Vec<LoaderVersion> = get(client, &path).await?;
+        let versions = versions.into_iter().map(|version| version.loader).collect();
+        match latest_stable(versions) {
+            Some(version) => version,
+            None => {
+                eprintln!("Warning: no fabric loader is available for {}", game);
+                return Ok(None);
+            }
+        }
+    } else {
+        loader.to_owned()
+    };
+
+    let installer = if installer == "latest" {
+        let versions: Vec<Version> = get(client, "v2/versions/installer").await?;
+        match latest_stable(versions) {
+            Some(version) => version,
+            None => return Ok(None),
+        }
+    } else {
+        installer.to_owned()
+    };
+
+    Ok(Some(Versions {
+        game,
+        loader,
+        installer,
+    }))
+}
+
+/// Fabric meta lists versions newest first.
+fn latest_stable(versions: Vec<Version>) -> Option<String> {
+    let stable = versions.iter().find(|version| version.stable);
+    stable
+        .or_else(|| versions.first())
+        .map(|version| version.version.clone())
+}
+
+async fn get<T: serde::de::DeserializeOwned>(client: &reqwest::Client, path: &str) -> Result<T> {
+    let url = format!("{}/{}", BASE_URL, path);
+    let response = client.get(&url).send().await?.error_for_status()?;
+    Ok(response.json().await?)
+}
+
+#[derive(Deserialize, Debug)]
+struct Version {
+    version: String,
+    stable: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct LoaderVersion {
+    loader: Version,
+}
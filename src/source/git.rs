@@ -0,0 +1,479 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+
+use sha1::{Digest, Sha1};
+use tokio::process::Command;
+use tokio::sync::Mutex;
+
+use crate::{cache, config, source, Error, Result};
+
+/// Loads the tree of the given ref, keyed as `<key>/<path>`, or a zip of it keyed as `<key>` if `zip` is set.
+pub async fn load(
+    client: &Client,
+    cache: &mut cache::Loader,
+    key: &str,
+    checkout: Checkout,
+    transform: &config::Transform,
+) -> Result<Vec<(String, cache::Reference)>> {
+    let url = checkout.url.as_str();
+    let commit = match client.resolve_commit(url, &checkout.reference).await? {
+        Some(commit) => commit,
+        None => existing_commit(cache, key, checkout.zip).await?,
+    };
+
+    let tree = match &checkout.subdir {
+        Some(subdir) => format!("{}:{}", commit, subdir.trim_matches('/')),
+        None => commit.clone(),
+    };
+    let token = cache::Token::Git(commit.clone());
+
+    if checkout.zip {
+        use cache::UpdateResult::*;
+        let reference = match cache.entry(key).try_update(token) {
+            Mismatch(updater) => {
+                let repository = client.fetch(url, &commit).await?;
+                let bytes = client
+                    .git(Some(&repository), &["archive", "--format=zip", &tree])
+                    .await?;
+                let file = source::File {
+                    name: format!("{}.zip", key),
+                    bytes: bytes.into(),
+                };
+
+                match transform.apply(file).await? {
+                    Some(file) => updater.update(file).await?,
+                    None => return Err(Error::MissingArtifact),
+                }
+            }
+            Match(reference) => reference,
+        };
+        return Ok(vec![(key.to_owned(), reference)]);
+    }
+
+    // the files are listed from the local clone even if nothing changed, so the commit always has to be available
+    let repository = client.fetch(url, &commit).await?;
+    let listing = client
+        .git(Some(&repository), &["ls-tree", "-r", "-z", &tree])
+        .await?;
+
+    let mut references = Vec::new();
+
+    for blob in parse_tree(&listing) {
+        let file_key = format!("{}/{}", key, blob.path);
+
+        use cache::UpdateResult::*;
+        let reference = match cache
+            .entry(file_key.clone())
+            .try_update(cache::Token::Git(blob.id.clone()))
+        {
+            Mismatch(updater) => {
+                let bytes = client
+                    .git(Some(&repository), &["cat-file", "blob", &blob.id])
+                    .await?;
+                let file = source::File {
+                    name: blob.path,
+                    bytes: bytes.into(),
+                };
+                updater.update(file).await?
+            }
+            Match(reference) => reference,
+        };
+        references.push((file_key, reference));
+    }
+
+    // the commit is kept as an internal entry so that it can be compared against without listing the tree
+    use cache::UpdateResult::*;
+    let root = match cache.entry(key).internal().try_update(token) {
+        Mismatch(updater) => {
+            let file = source::File {
+                name: key.to_owned(),
+                bytes: commit.into(),
+            };
+            updater.update(file).await?
+        }
+        Match(reference) => reference,
+    };
+    references.insert(0, (key.to_owned(), root));
+
+    Ok(references)
+}
+
+pub async fn resolve(client: &Client, url: &str, reference: &str) -> Result<Option<cache::Token>> {
+    let commit = client.resolve_commit(url, reference).await?;
+    Ok(commit.map(cache::Token::Git))
+}
+
+#[derive(Clone, Debug)]
+pub struct Checkout {
+    pub url: String,
+    /// A branch, tag, full commit hash or `HEAD`.
+    pub reference: String,
+    /// A directory within the repository to load instead of the whole tree.
+    pub subdir: Option<String>,
+    pub zip: bool,
+}
+
+/// Falls back to the last loaded commit when the ref no longer exists.
+async fn existing_commit(cache: &mut cache::Loader, key: &str, zip: bool) -> Result<String> {
+    if zip {
+        return Err(Error::MissingArtifact);
+    }
+
+    let existing = cache.entry(key).internal().get_existing();
+    match existing {
+        Some(existing) => Ok(String::from_utf8_lossy(&existing.read().await?).into_owned()),
+        None => Err(Error::MissingArtifact),
+    }
+}
+
+struct Blob {
+    id: String,
+    path: String,
+}
+
+/// Parses the output of `git ls-tree -r -z`, where each entry is given as `<mode> <type> <id>\t<path>`.
+fn parse_tree(listing: &[u8]) -> Vec<Blob> {
+    let mut blobs = Vec::new();
+
+    for entry in listing.split(|byte| *byte == 0) {
+        let entry = String::from_utf8_lossy(entry);
+        let Some((info, path)) = entry.split_once('\t') else {
+            continue;
+        };
+
+        match info.split(' ').collect::<Vec<&str>>().as_slice() {
+            ["120000", "blob", _] => {
+                eprintln!("Warning: skipping symbolic link {} in git repository", path)
+            }
+            [_, "blob", id] => blobs.push(Blob {
+                id: id.to_string(),
+                path: path.to_owned(),
+            }),
+            // submodules are listed as commits and not loaded
+            _ => (),
+        }
+    }
+
+    blobs
+}
+
+#[derive(Clone)]
+pub struct Client {
+    root: PathBuf,
+    /// Configuration passed to every git command as `-c <name>=<value>`.
+    config: Vec<String>,
+    // fetches into the same repository can not run concurrently
+    lock: Arc<Mutex<()>>,
+}
+
+impl Client {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Client {
+        Client {
+            root: root.into(),
+            config: Vec::new(),
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    #[cfg(test)]
+    fn with_config<P: Into<PathBuf>>(root: P, config: &[&str]) -> Client {
+        Client {
+            config: config.iter().map(|option| option.to_string()).collect(),
+            ..Client::new(root)
+        }
+    }
+
+    /// Resolves a branch, tag or `HEAD` of the remote repository to a commit, unless it already is a full commit hash.
+    async fn resolve_commit(&self, url: &str, reference: &str) -> Result<Option<String>> {
+        if is_commit_hash(reference) {
+            return Ok(Some(reference.to_lowercase()));
+        }
+
+        let output = self.git(None, &["ls-remote", "--", url]).await?;
+        let output = String::from_utf8_lossy(&output);
+        let refs: Vec<(&str, &str)> = output
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .collect();
+
+        // annotated tags are listed a second time as `<tag>^{}`, pointing to their commit
+        let candidates = [
+            reference.to_owned(),
+            format!("refs/heads/{}", reference),
+            format!("refs/tags/{}^{{}}", reference),
+            format!("refs/tags/{}", reference),
+        ];
+        let commit = candidates.iter().find_map(|candidate| {
+            refs.iter()
+                .find(|(_, name)| name == candidate)
+                .map(|(commit, _)| commit.to_string())
+        });
+
+        if commit.is_none() {
+            eprintln!("Warning: {} has no ref {}", url, reference);
+        }
+
+        Ok(commit)
+    }
+
+    /// Fetches the given commit into the local clone of the repository if it is not present yet, returning its path.
+    async fn fetch(&self, url: &str, commit: &str) -> Result<PathBuf> {
+        let _lock = self.lock.lock().await;
+
+        let repository = self.root.join(hex::encode(Sha1::digest(url.as_bytes())));
+        if !repository.exists() {
+            let path = repository.to_string_lossy();
+            self.git(None, &["init", "--quiet", "--bare", &path])
+                .await?;
+        }
+
+        let object = format!("{}^{{commit}}", commit);
+        if self
+            .git(Some(&repository), &["cat-file", "-e", &object])
+            .await
+            .is_err()
+        {
+            println!("fetching {} from {}...", commit, url);
+            let fetched = self
+                .git(
+                    Some(&repository),
+                    &[
+                        "fetch",
+                        "--quiet",
+                        "--depth=1",
+                        "--no-tags",
+                        "--no-write-fetch-head",
+                        "--",
+                        url,
+                        commit,
+                    ],
+                )
+                .await;
+
+            // servers without `uploadpack.allowReachableSHA1InWant` only serve commits that a ref points to,
+            // in which case the whole history has to be fetched to find older commits
+            if let Err(err) = fetched {
+                eprintln!(
+                    "Warning: failed to fetch {} directly, fetching all branches and tags instead: {}",
+                    commit, err
+                );
+
+                let mut args = vec!["fetch", "--quiet", "--no-tags", "--no-write-fetch-head"];
+                if repository.join("shallow").exists() {
+                    args.push("--unshallow");
+                }
+                args.extend([
+                    "--",
+                    url,
+                    "+refs/heads/*:refs/fetched/heads/*",
+                    "+refs/tags/*:refs/fetched/tags/*",
+                ]);
+                self.git(Some(&repository), &args).await?;
+            }
+        }
+
+        Ok(repository)
+    }
+
+    async fn git(&self, repository: Option<&Path>, args: &[&str]) -> Result<Vec<u8>> {
+        let mut command = Command::new("git");
+        for option in &self.config {
+            command.arg("-c").arg(option);
+        }
+        if let Some(repository) = repository {
+            command.arg("--git-dir").arg(repository);
+        }
+
+        // never wait for credentials to be entered
+        let output = command
+            .args(args)
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .stdin(Stdio::null())
+            .output()
+            .await?;
+
+        if output.status.success() {
+            Ok(output.stdout)
+        } else {
+            Err(Error::Git(format!(
+                "git {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )))
+        }
+    }
+}
+
+fn is_commit_hash(reference: &str) -> bool {
+    reference.len() == 40 && reference.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+    use crate::test_fixture::TempDir;
+
+    /// A repository with two commits on `main`, where the first is tagged as `v1`.
+    struct Fixture {
+        root: TempDir,
+        url: String,
+        first_commit: String,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Fixture {
+            let root = TempDir::new(&format!("git-{}", name));
+            let repository = root.path().join("repository");
+            std::fs::create_dir_all(repository.join("dir")).unwrap();
+
+            let mut fixture = Fixture {
+                url: format!("file://{}", repository.display()),
+                root,
+                first_commit: String::new(),
+            };
+
+            fixture.git(&["init", "--quiet", "--initial-branch=main"]);
+            fixture.commit(&[("a.txt", "one"), ("dir/b.txt", "b")]);
+            fixture.git(&["tag", "--annotate", "v1", "--message", "v1"]);
+            fixture.first_commit = fixture.git(&["rev-parse", "HEAD"]);
+            fixture.commit(&[("a.txt", "two")]);
+
+            fixture
+        }
+
+        fn commit(&self, files: &[(&str, &str)]) {
+            for (path, contents) in files {
+                std::fs::write(self.root.path().join("repository").join(path), contents).unwrap();
+            }
+            self.git(&["add", "--all"]);
+            self.git(&["commit", "--quiet", "--message", "commit"]);
+        }
+
+        fn git(&self, args: &[&str]) -> String {
+            let output = process::Command::new("git")
+                .arg("-C")
+                .arg(self.root.path().join("repository"))
+                .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "git {:?} failed", args);
+            String::from_utf8(output.stdout).unwrap().trim().to_owned()
+        }
+
+        async fn load(
+            &self,
+            reference: &str,
+            subdir: Option<&str>,
+            zip: bool,
+        ) -> Vec<(String, Vec<u8>, bool)> {
+            let cache_root = self.root.path().join("cache");
+            // fetch like servers without `uploadpack.allowReachableSHA1InWant` do, which refuse unadvertised commits
+            let client =
+                Client::with_config(cache_root.join(".repositories"), &["protocol.version=0"]);
+            let mut cache = cache::Loader::open(&cache_root).await.unwrap();
+
+            let checkout = Checkout {
+                url: self.url.clone(),
+                reference: reference.to_owned(),
+                subdir: subdir.map(str::to_owned),
+                zip,
+            };
+            let transform = config::Transform::Direct;
+            let references = load(&client, &mut cache, "configs", checkout, &transform)
+                .await
+                .unwrap();
+
+            let mut files = Vec::new();
+            for (key, reference) in references {
+                let bytes = reference.read().await.unwrap();
+                files.push((key, bytes, reference.changed()));
+            }
+
+            cache.close().await.unwrap();
+            files
+        }
+    }
+
+    fn contents<'a>(files: &'a [(String, Vec<u8>, bool)], key: &str) -> Option<&'a str> {
+        files
+            .iter()
+            .find(|(file_key, _, _)| file_key == key)
+            .map(|(_, bytes, _)| std::str::from_utf8(bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn branches_are_loaded() {
+        let fixture = Fixture::new("branch");
+        let files = fixture.load("main", None, false).await;
+
+        let keys: Vec<&str> = files.iter().map(|(key, _, _)| key.as_str()).collect();
+        assert_eq!(keys, ["configs", "configs/a.txt", "configs/dir/b.txt"]);
+        assert_eq!(contents(&files, "configs/a.txt"), Some("two"));
+        assert_eq!(contents(&files, "configs/dir/b.txt"), Some("b"));
+    }
+
+    #[tokio::test]
+    async fn tags_are_loaded() {
+        let fixture = Fixture::new("tag");
+        let files = fixture.load("v1", None, false).await;
+
+        assert_eq!(contents(&files, "configs/a.txt"), Some("one"));
+        assert_eq!(
+            contents(&files, "configs"),
+            Some(fixture.first_commit.as_str())
+        );
+    }
+
+    #[tokio::test]
+    async fn older_commits_are_loaded() {
+        let fixture = Fixture::new("commit");
+
+        // the repository is shallow after loading the branch, and the older commit is not advertised
+        fixture.load("main", None, false).await;
+        let files = fixture.load(&fixture.first_commit, None, false).await;
+
+        assert_eq!(contents(&files, "configs/a.txt"), Some("one"));
+    }
+
+    #[tokio::test]
+    async fn subdirectories_are_loaded() {
+        let fixture = Fixture::new("subdir");
+        let files = fixture.load("main", Some("dir/"), false).await;
+
+        let keys: Vec<&str> = files.iter().map(|(key, _, _)| key.as_str()).collect();
+        assert_eq!(keys, ["configs", "configs/b.txt"]);
+    }
+
+    #[tokio::test]
+    async fn trees_are_loaded_as_zip() {
+        let fixture = Fixture::new("zip");
+        let files = fixture.load("main", Some("dir"), true).await;
+
+        assert_eq!(files.len(), 1);
+        let (key, bytes, _) = &files[0];
+        assert_eq!(key, "configs");
+        assert!(bytes.starts_with(b"PK"));
+    }
+
+    #[tokio::test]
+    async fn unchanged_files_are_not_updated() {
+        let fixture = Fixture::new("unchanged");
+        fixture.load("main", None, false).await;
+
+        let files = fixture.load("main", None, false).await;
+        assert!(files.iter().all(|(_, _, changed)| !changed));
+
+        fixture.commit(&[("a.txt", "three")]);
+        let files = fixture.load("main", None, false).await;
+        let changed: Vec<&str> = files
+            .iter()
+            .filter(|(_, _, changed)| *changed)
+            .map(|(key, _, _)| key.as_str())
+            .collect();
+        assert_eq!(changed, ["configs", "configs/a.txt"]);
+    }
+}
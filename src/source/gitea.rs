@@ -0,0 +1,383 @@
+use std::cmp;
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::{cache, config, source, Error, Result};
+
+pub async fn load<'a>(
+    client: &Client,
+    cache: cache::Entry<'a>,
+    owner: &str,
+    repository: &str,
+    filter: Filter,
+    transform: &config::Transform,
+) -> Result<cache::Reference> {
+    let latest_artifact = get_latest_artifact(client, owner, repository, &filter).await?;
+
+    if let Some(artifact) = latest_artifact {
+        use cache::UpdateResult::*;
+        match cache.try_update(cache::Token::ArtifactId(artifact.id)) {
+            Mismatch(updater) => {
+                println!("downloading {}...", artifact.archive_download_url);
+
+                let response = client.get(&artifact.archive_download_url).await?;
+                let bytes = response.bytes().await?;
+                let file = source::File {
+                    name: format!("{}.zip", artifact.name),
+                    bytes,
+                };
+
+                if let Some(file) = transform.apply(file).await? {
+                    Ok(updater.update(file).await?)
+                } else {
+                    Err(Error::MissingArtifact)
+                }
+            }
+            Match(reference) => Ok(reference),
+        }
+    } else {
+        cache.get_existing().ok_or(Error::MissingArtifact)
+    }
+}
+
+pub async fn resolve(
+    client: &Client,
+    owner: &str,
+    repository: &str,
+    filter: Filter,
+) -> Result<Option<cache::Token>> {
+    let latest_artifact = get_latest_artifact(client, owner, repository, &filter).await?;
+    Ok(latest_artifact.map(|artifact| cache::Token::ArtifactId(artifact.id)))
+}
+
+async fn get_latest_artifact(
+    client: &Client,
+    owner: &str,
+    repository: &str,
+    filter: &Filter,
+) -> Result<Option<Artifact>> {
+    let repository_url = format!(
+        "{}/api/v1/repos/{}/{}",
+        filter.instance.trim_end_matches('/'),
+        owner,
+        repository
+    );
+
+    let mut query = vec![("status", "success"), ("limit", Filter::MAX_RUNS)];
+    if let Some(branch) = &filter.branch {
+        query.push(("branch", branch));
+    }
+    let url = format!("{}/actions/runs", repository_url);
+    let url = reqwest::Url::parse_with_params(&url, &query).expect("malformed gitea url");
+    let response: WorkflowRunsResponse = client.get_json(url.as_str()).await?;
+
+    let mut workflow_runs = response.workflow_runs;
+    workflow_runs.sort_by_key(|run| cmp::Reverse(run.id));
+
+    // the instance may not support filtering runs, so check them here as well
+    let workflow_runs = workflow_runs
+        .into_iter()
+        .filter(|run| filter.test_branch(&run.head_branch))
+        .filter(|run| run.conclusion.as_deref() == Some("success"));
+
+    for run in workflow_runs {
+        let url = format!("{}/actions/runs/{}/artifacts", repository_url, run.id);
+        let response: ArtifactsResponse = client.get_json(&url).await?;
+
+        let mut artifacts = response.artifacts;
+        artifacts.sort_by_key(|artifact| cmp::Reverse(artifact.id));
+
+        let artifact = artifacts
+            .into_iter()
+            .find(|artifact| filter.test_artifact(&artifact.name));
+
+        if let Some(artifact) = artifact {
+            // artifacts expire after the instance's retention period, so those of older runs have expired as well
+            return Ok(Some(artifact).filter(|artifact| !artifact.expired));
+        }
+    }
+
+    Ok(None)
+}
+
+#[derive(Clone, Debug)]
+pub struct Filter {
+    /// The base url of the Gitea or Forgejo instance, such as `https://codeberg.org`.
+    pub instance: String,
+    pub branch: Option<String>,
+    pub artifact: Option<String>,
+}
+
+impl Filter {
+    const MAX_RUNS: &'static str = "50";
+
+    #[inline]
+    pub fn test_branch(&self, branch: &str) -> bool {
+        self.branch.as_ref().map(|r| r == branch).unwrap_or(true)
+    }
+
+    #[inline]
+    pub fn test_artifact(&self, artifact: &str) -> bool {
+        self.artifact
+            .as_ref()
+            .map(|r| r == artifact)
+            .unwrap_or(true)
+    }
+}
+
+#[derive(Clone)]
+pub struct Client {
+    client: Arc<reqwest::Client>,
+}
+
+impl Client {
+    pub fn new(token: Option<String>) -> Client {
+        let mut default_headers = reqwest::header::HeaderMap::new();
+
+        if let Some(token) = token {
+            let authorization = format!("token {}", token);
+            default_headers.insert(
+                reqwest::header::AUTHORIZATION,
+                authorization.parse().unwrap(),
+            );
+        }
+
+        let client = reqwest::Client::builder()
+            .gzip(true)
+            .user_agent("server-wrapper (https://github.com/NucleoidMC/server-wrapper)")
+            .default_headers(default_headers)
+            .build()
+            .unwrap();
+
+        Client {
+            client: Arc::new(client),
+        }
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let response = self.get(url).await?;
+        Ok(response.json().await?)
+    }
+
+    #[inline]
+    pub async fn get(&self, url: &str) -> Result<reqwest::Response> {
+        Ok(self.client.get(url).send().await?.error_for_status()?)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+struct WorkflowRunsResponse {
+    total_count: usize,
+    workflow_runs: Vec<WorkflowRun>,
+}
+
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+struct WorkflowRun {
+    id: usize,
+    head_branch: String,
+    head_sha: String,
+    status: Option<String>,
+    conclusion: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+struct ArtifactsResponse {
+    total_count: usize,
+    artifacts: Vec<Artifact>,
+}
+
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+struct Artifact {
+    id: usize,
+    name: String,
+    size_in_bytes: usize,
+    archive_download_url: String,
+    expired: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::test_fixture::{seed, TempDir};
+    use crate::test_server::TestServer;
+
+    const REPOSITORY: &str = "/api/v1/repos/owner/repository";
+
+    struct Run {
+        id: usize,
+        branch: &'static str,
+        conclusion: &'static str,
+        artifacts: Vec<Value>,
+    }
+
+    fn run(id: usize, artifacts: Vec<Value>) -> Run {
+        Run {
+            id,
+            branch: "main",
+            conclusion: "success",
+            artifacts,
+        }
+    }
+
+    fn artifact(id: usize, name: &str, expired: bool) -> Value {
+        json!({
+            "id": id,
+            "name": name,
+            "size_in_bytes": 4,
+            "archive_download_url": format!("{{url}}/download/{}", id),
+            "expired": expired,
+        })
+    }
+
+    fn filter(server: &TestServer, artifact: &str) -> Filter {
+        Filter {
+            instance: server.url.clone(),
+            branch: Some("main".to_owned()),
+            artifact: Some(artifact.to_owned()),
+        }
+    }
+
+    async fn server(runs: &[Run]) -> TestServer {
+        let workflow_runs: Vec<Value> = runs
+            .iter()
+            .map(|run| {
+                json!({
+                    "id": run.id,
+                    "head_branch": run.branch,
+                    "head_sha": "abc",
+                    "status": "completed",
+                    "conclusion": run.conclusion,
+                })
+            })
+            .collect();
+
+        let mut routes = vec![
+            (
+                format!("{}/actions/runs", REPOSITORY),
+                json!({ "total_count": runs.len(), "workflow_runs": workflow_runs }).to_string(),
+            ),
+            ("/download/22".to_owned(), "PK22".to_owned()),
+        ];
+        for run in runs {
+            let artifacts =
+                json!({ "total_count": run.artifacts.len(), "artifacts": run.artifacts });
+            routes.push((
+                format!("{}/actions/runs/{}/artifacts", REPOSITORY, run.id),
+                artifacts.to_string(),
+            ));
+        }
+
+        TestServer::start(
+            routes
+                .iter()
+                .map(|(path, body)| (path.as_str(), body.clone())),
+        )
+        .await
+    }
+
+    async fn load_artifact(
+        server: &TestServer,
+        loader: &mut cache::Loader,
+        artifact: &str,
+    ) -> Result<cache::Reference> {
+        load(
+            &Client::new(None),
+            loader.entry("mod"),
+            "owner",
+            "repository",
+            filter(server, artifact),
+            &config::Transform::Direct,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn newest_artifact_is_loaded() {
+        let server = server(&[
+            run(
+                2,
+                vec![artifact(21, "build", false), artifact(22, "build", false)],
+            ),
+            run(1, vec![artifact(11, "build", false)]),
+        ])
+        .await;
+
+        let token = resolve(
+            &Client::new(None),
+            "owner",
+            "repository",
+            filter(&server, "build"),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(token, Some(cache::Token::ArtifactId(22))));
+
+        let root = TempDir::new("gitea-load");
+        let mut loader = cache::Loader::open(root.path()).await.unwrap();
+        let reference = load_artifact(&server, &mut loader, "build").await.unwrap();
+        assert_eq!(reference.read().await.unwrap(), b"PK22");
+    }
+
+    #[tokio::test]
+    async fn runs_are_filtered_by_branch_and_conclusion() {
+        let server = server(&[
+            Run {
+                branch: "feature",
+                ..run(4, vec![artifact(41, "build", false)])
+            },
+            Run {
+                conclusion: "failure",
+                ..run(3, vec![artifact(31, "build", false)])
+            },
+            run(2, vec![artifact(21, "test", false)]),
+            run(1, vec![artifact(11, "build", false)]),
+        ])
+        .await;
+
+        let token = resolve(
+            &Client::new(None),
+            "owner",
+            "repository",
+            filter(&server, "build"),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(token, Some(cache::Token::ArtifactId(11))));
+    }
+
+    #[tokio::test]
+    async fn missing_artifacts_keep_the_existing_artifact() {
+        let server = server(&[
+            run(3, vec![artifact(31, "test", false)]),
+            run(2, vec![artifact(21, "build", true)]),
+        ])
+        .await;
+
+        let token = resolve(
+            &Client::new(None),
+            "owner",
+            "repository",
+            filter(&server, "build"),
+        )
+        .await
+        .unwrap();
+        assert!(token.is_none());
+
+        let root = TempDir::new("gitea-missing");
+        let mut loader = cache::Loader::open(root.path()).await.unwrap();
+        let result = load_artifact(&server, &mut loader, "build").await;
+        assert!(matches!(result, Err(Error::MissingArtifact)));
+
+        seed(&mut loader, "mod", cache::Token::ArtifactId(11), "PK11").await;
+
+        let reference = load_artifact(&server, &mut loader, "build").await.unwrap();
+        assert_eq!(reference.read().await.unwrap(), b"PK11");
+    }
+}
@@ -38,49 +38,70 @@ pub async fn load<'a>(
     }
 }
 
+pub async fn resolve(
+    client: &Client,
+    owner: &str,
+    repository: &str,
+    filter: Filter,
+) -> Result<Option<cache::Token>> {
+    let latest_artifact = get_latest_artifact(client, owner, repository, filter).await?;
+    Ok(latest_artifact.map(|(id, _, _)| cache::Token::ArtifactId(id)))
+}
+
 async fn get_latest_artifact(
     client: &Client,
     owner: &str,
     repository: &str,
     filter: Filter,
 ) -> Result<Option<(usize, String, String)>> {
-    // TODO: we're not handling pagination, which means we rely on results being ordered by newest!
+    let mut url = Some(client.workflow_runs_url(owner, repository, &filter));
+    let mut pages = 0;
 
-    let mut workflow_runs = client
-        .get_workflow_runs(owner, repository)
-        .await?
-        .workflow_runs;
-    workflow_runs.sort_by_key(|run| cmp::Reverse(run.updated_at));
+    while let Some(page_url) = url.take() {
+        // don't walk through the entire history of a repository when nothing matches
+        if pages >= Filter::MAX_PAGES {
+            break;
+        }
+        pages += 1;
 
-    let workflow_runs = workflow_runs
-        .into_iter()
-        .filter(|run| filter.test_workflow(&run.name))
-        .filter(|run| filter.test_branch(&run.head_branch));
-
-    for run in workflow_runs {
-        let mut artifacts = match &run.artifacts_url {
-            Some(_) => {
-                client
-                    .get_artifacts(owner, repository, &run)
-                    .await?
-                    .artifacts
-            }
-            None => continue,
-        };
-        artifacts.sort_by_key(|artifact| cmp::Reverse(artifact.updated_at));
+        let (response, next_url): (WorkflowRunsResponse, _) = client.get_page(&page_url).await?;
+        url = next_url;
 
-        let artifacts = artifacts
+        let mut workflow_runs = response.workflow_runs;
+        workflow_runs.sort_by_key(|run| cmp::Reverse(run.updated_at));
+
+        let workflow_runs = workflow_runs
             .into_iter()
-            .filter(|artifact| filter.test_artifact(&artifact.name));
+            .filter(|run| filter.test_workflow(&run.name))
+            .filter(|run| filter.test_branch(&run.head_branch))
+            .filter(|run| filter.test_conclusion(run.conclusion.as_deref()))
+            .filter(|run| filter.test_commit(&run.head_sha));
 
-        for artifact in artifacts {
-            // early-exit when we find an expired build: we know nothing older will still be around
-            if artifact.expired {
-                return Ok(None);
-            }
+        for run in workflow_runs {
+            let mut artifacts = match &run.artifacts_url {
+                Some(_) => {
+                    client
+                        .get_artifacts(owner, repository, &run)
+                        .await?
+                        .artifacts
+                }
+                None => continue,
+            };
+            artifacts.sort_by_key(|artifact| cmp::Reverse(artifact.updated_at));
+
+            let artifacts = artifacts
+                .into_iter()
+                .filter(|artifact| filter.test_artifact(&artifact.name));
+
+            for artifact in artifacts {
+                // early-exit when we find an expired build: we know nothing older will still be around
+                if artifact.expired {
+                    return Ok(None);
+                }
 
-            if let Some(url) = artifact.archive_download_url {
-                return Ok(Some((artifact.id, url, artifact.name)));
+                if let Some(url) = artifact.archive_download_url {
+                    return Ok(Some((artifact.id, url, artifact.name)));
+                }
             }
         }
     }
@@ -90,14 +111,38 @@ async fn get_latest_artifact(
 
 #[derive(Clone, Debug)]
 pub struct Filter {
+    /// Either the name of a workflow or the file name of its definition, such as `build.yml`.
     pub workflow: Option<String>,
     pub branch: Option<String>,
     pub artifact: Option<String>,
+    pub event: Option<String>,
+    /// Whether only runs that completed successfully should be considered.
+    pub require_success: bool,
+    /// The (possibly abbreviated) head commit SHA of the run to load.
+    pub commit: Option<String>,
 }
 
 impl Filter {
+    const MAX_PAGES: usize = 10;
+    const DEFAULT_EVENT: &'static str = "push";
+
+    /// Returns the workflow file name if the workflow was given as one, in which case runs can be filtered by GitHub.
+    pub fn workflow_file(&self) -> Option<&str> {
+        self.workflow
+            .as_deref()
+            .filter(|workflow| workflow.ends_with(".yml") || workflow.ends_with(".yaml"))
+    }
+
+    pub fn event(&self) -> &str {
+        self.event.as_deref().unwrap_or(Filter::DEFAULT_EVENT)
+    }
+
     #[inline]
     pub fn test_workflow(&self, workflow: &str) -> bool {
+        if self.workflow_file().is_some() {
+            return true;
+        }
+
         self.workflow
             .as_ref()
             .map(|r| r == workflow)
@@ -109,6 +154,24 @@ impl Filter {
         self.branch.as_ref().map(|r| r == branch).unwrap_or(true)
     }
 
+    #[inline]
+    pub fn test_conclusion(&self, conclusion: Option<&str>) -> bool {
+        !self.require_success || conclusion == Some("success")
+    }
+
+    #[inline]
+    pub fn test_commit(&self, sha: &str) -> bool {
+        self.commit
+            .as_ref()
+            .map(|commit| sha.starts_with(&commit.to_lowercase()))
+            .unwrap_or(true)
+    }
+
+    /// Returns the commit SHA if it is given in full, in which case runs can be filtered by GitHub.
+    pub fn full_commit(&self) -> Option<&str> {
+        self.commit.as_deref().filter(|commit| commit.len() == 40)
+    }
+
     #[inline]
     pub fn test_artifact(&self, artifact: &str) -> bool {
         self.artifact
@@ -118,6 +181,123 @@ impl Filter {
     }
 }
 
+pub async fn load_release<'a>(
+    client: &Client,
+    cache: cache::Entry<'a>,
+    owner: &str,
+    repository: &str,
+    filter: ReleaseFilter,
+    transform: &config::Transform,
+) -> Result<cache::Reference> {
+    let asset = get_release_asset(client, owner, repository, &filter).await?;
+
+    if let Some(asset) = asset {
+        use cache::UpdateResult::*;
+        match cache.try_update(asset.token()) {
+            Mismatch(updater) => {
+                let response = client.get_asset(&asset.url).await?;
+                let bytes = response.bytes().await?;
+                let file = source::File {
+                    name: asset.name,
+                    bytes,
+                };
+
+                if let Some(file) = transform.apply(file).await? {
+                    Ok(updater.update(file).await?)
+                } else {
+                    Err(Error::MissingArtifact)
+                }
+            }
+            Match(reference) => Ok(reference),
+        }
+    } else {
+        cache.get_existing().ok_or(Error::MissingArtifact)
+    }
+}
+
+pub async fn resolve_release(
+    client: &Client,
+    owner: &str,
+    repository: &str,
+    filter: ReleaseFilter,
+) -> Result<Option<cache::Token>> {
+    let asset = get_release_asset(client, owner, repository, &filter).await?;
+    Ok(asset.map(|asset| asset.token()))
+}
+
+async fn get_release_asset(
+    client: &Client,
+    owner: &str,
+    repository: &str,
+    filter: &ReleaseFilter,
+) -> Result<Option<ReleaseAsset>> {
+    if filter.release != ReleaseFilter::LATEST {
+        let release = client
+            .get_release_by_tag(owner, repository, &filter.release)
+            .await?;
+        return Ok(find_release_asset(release, filter));
+    }
+
+    // releases are listed newest first
+    let mut url = Some(client.releases_url(owner, repository));
+    let mut pages = 0;
+
+    while let Some(page_url) = url.take() {
+        if pages >= Filter::MAX_PAGES {
+            break;
+        }
+        pages += 1;
+
+        let (releases, next_url): (Vec<Release>, _) = client.get_page(&page_url).await?;
+        url = next_url;
+
+        // a release may not have its assets uploaded yet, so fall back to older releases
+        for release in releases {
+            if let Some(asset) = find_release_asset(release, filter) {
+                return Ok(Some(asset));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+fn find_release_asset(release: Release, filter: &ReleaseFilter) -> Option<ReleaseAsset> {
+    if release.draft || !filter.test_prerelease(release.prerelease) {
+        return None;
+    }
+
+    release
+        .assets
+        .into_iter()
+        .find(|asset| filter.test_asset(&asset.name))
+}
+
+#[derive(Clone, Debug)]
+pub struct ReleaseFilter {
+    /// The tag of the release to load, or `latest` for the newest release.
+    pub release: String,
+    pub asset: Option<config::Pattern>,
+    pub prereleases: bool,
+}
+
+impl ReleaseFilter {
+    const LATEST: &'static str = "latest";
+
+    #[inline]
+    pub fn test_prerelease(&self, prerelease: bool) -> bool {
+        self.prereleases || !prerelease || self.release != ReleaseFilter::LATEST
+    }
+
+    #[inline]
+    pub fn test_asset(&self, asset: &str) -> bool {
+        self.asset
+            .as_ref()
+            .map(|pattern| pattern.matches(asset))
+            .unwrap_or(true)
+    }
+}
+
 #[derive(Clone)]
 pub struct Client {
     client: Arc<reqwest::Client>,
@@ -149,46 +329,136 @@ impl Client {
         }
     }
 
-    async fn get_workflow_runs(
+    fn workflow_runs_url(&self, owner: &str, repository: &str, filter: &Filter) -> reqwest::Url {
+        let url = match filter.workflow_file() {
+            Some(workflow) => format!(
+                "{}/repos/{}/{}/actions/workflows/{}/runs",
+                Client::BASE_URL,
+                owner,
+                repository,
+                workflow
+            ),
+            None => format!(
+                "{}/repos/{}/{}/actions/runs",
+                Client::BASE_URL,
+                owner,
+                repository
+            ),
+        };
+
+        // Github documents the exclude_pull_requests parameter, but it doesn't seem to have any effect,
+        // so also filter by event, which defaults to push to exclude runs with event=pull_request
+        let mut query = vec![
+            ("event", filter.event()),
+            ("exclude_pull_requests", "true"),
+            ("per_page", "100"),
+        ];
+        if filter.require_success {
+            query.push(("status", "success"));
+        }
+        if let Some(branch) = &filter.branch {
+            query.push(("branch", branch));
+        }
+        if let Some(commit) = filter.full_commit() {
+            query.push(("head_sha", commit));
+        }
+
+        reqwest::Url::parse_with_params(&url, &query).expect("malformed github url")
+    }
+
+    /// Requests a page of a listing such as workflow runs, returning it along with the url of the next page.
+    async fn get_page<T: serde::de::DeserializeOwned>(
+        &self,
+        url: &reqwest::Url,
+    ) -> Result<(T, Option<reqwest::Url>)> {
+        let response = self
+            .client
+            .get(url.clone())
+            .send()
+            .await?
+            .error_for_status()?;
+        let next_url = next_page_url(response.headers());
+        Ok((response.json().await?, next_url))
+    }
+
+    async fn get_artifacts(
         &self,
         owner: &str,
         repository: &str,
-    ) -> Result<WorkflowRunsResponse> {
-        // Github documents the exclude_pull_requests parameter, but it doesn't seem to have any effect,
-        // so also use event=push to exclude runs with event=pull_request
+        run: &WorkflowRun,
+    ) -> Result<ArtifactsResponse> {
         let url = format!(
-            "{}/repos/{}/{}/actions/runs?event=push&exclude_pull_requests=true",
+            "{}/repos/{}/{}/actions/runs/{}/artifacts?per_page=100",
             Client::BASE_URL,
             owner,
-            repository
+            repository,
+            run.id
         );
         let response = self.get(&url).await?;
         Ok(response.json().await?)
     }
 
-    async fn get_artifacts(
+    fn releases_url(&self, owner: &str, repository: &str) -> reqwest::Url {
+        let url = format!(
+            "{}/repos/{}/{}/releases?per_page=100",
+            Client::BASE_URL,
+            owner,
+            repository
+        );
+        reqwest::Url::parse(&url).expect("malformed github url")
+    }
+
+    async fn get_release_by_tag(
         &self,
         owner: &str,
         repository: &str,
-        run: &WorkflowRun,
-    ) -> Result<ArtifactsResponse> {
+        tag: &str,
+    ) -> Result<Release> {
         let url = format!(
-            "{}/repos/{}/{}/actions/runs/{}/artifacts",
+            "{}/repos/{}/{}/releases/tags/{}",
             Client::BASE_URL,
             owner,
             repository,
-            run.id
+            source::encode_path_segment(tag)
         );
-        let response = self.get(&url).await?;
+        let response = self.get(&url).await?.error_for_status()?;
         Ok(response.json().await?)
     }
 
+    /// Downloads a release asset through the API, which unlike its browser download url also works for private repositories.
+    async fn get_asset(&self, url: &str) -> Result<reqwest::Response> {
+        let response = self
+            .client
+            .get(url)
+            .header(reqwest::header::ACCEPT, "application/octet-stream")
+            .send()
+            .await?;
+        Ok(response.error_for_status()?)
+    }
+
     #[inline]
     pub async fn get(&self, url: &str) -> Result<reqwest::Response> {
         Ok(self.client.get(url).send().await?)
     }
 }
 
+/// Finds the `rel="next"` url of a `Link` header, as used by GitHub for pagination.
+fn next_page_url(headers: &reqwest::header::HeaderMap) -> Option<reqwest::Url> {
+    let link = headers.get(reqwest::header::LINK)?.to_str().ok()?;
+    link.split(',').find_map(|link| {
+        let (url, params) = link.split_once(';')?;
+        let next = params
+            .split(';')
+            .any(|param| param.trim() == "rel=\"next\"");
+        if next {
+            let url = url.trim().strip_prefix('<')?.strip_suffix('>')?;
+            reqwest::Url::parse(url).ok()
+        } else {
+            None
+        }
+    })
+}
+
 #[derive(Deserialize, Debug)]
 #[allow(unused)]
 struct WorkflowRunsResponse {
@@ -202,6 +472,9 @@ struct WorkflowRun {
     id: usize,
     name: String,
     head_branch: String,
+    head_sha: String,
+    status: Option<String>,
+    conclusion: Option<String>,
     workflow_id: usize,
     artifacts_url: Option<String>,
     created_at: chrono::DateTime<chrono::Utc>,
@@ -229,3 +502,80 @@ struct Artifact {
     expires_at: chrono::DateTime<chrono::Utc>,
     updated_at: chrono::DateTime<chrono::Utc>,
 }
+
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+struct Release {
+    id: usize,
+    tag_name: String,
+    draft: bool,
+    prerelease: bool,
+    assets: Vec<ReleaseAsset>,
+}
+
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+struct ReleaseAsset {
+    id: usize,
+    name: String,
+    url: String,
+    size: usize,
+    digest: Option<String>,
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl ReleaseAsset {
+    /// Prefers the content digest so that re-uploading an identical asset is not treated as a change.
+    fn token(&self) -> cache::Token {
+        match self
+            .digest
+            .as_ref()
+            .and_then(|digest| digest.strip_prefix("sha256:"))
+        {
+            Some(sha256) => cache::Token::Sha256(sha256.to_owned()),
+            None => cache::Token::AssetId(self.id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::{HeaderMap, LINK};
+
+    use super::*;
+
+    fn next_page(link: &str) -> Option<String> {
+        let mut headers = HeaderMap::new();
+        headers.insert(LINK, link.parse().unwrap());
+        next_page_url(&headers).map(|url| url.to_string())
+    }
+
+    #[test]
+    fn next_page_is_found_among_links() {
+        let link = "<https://api.github.com/repositories/1/actions/runs?page=1>; rel=\"prev\", \
+            <https://api.github.com/repositories/1/actions/runs?page=3>; rel=\"next\", \
+            <https://api.github.com/repositories/1/actions/runs?page=5>; rel=\"last\"";
+        assert_eq!(
+            next_page(link).as_deref(),
+            Some("https://api.github.com/repositories/1/actions/runs?page=3")
+        );
+    }
+
+    #[test]
+    fn last_page_has_no_next_link() {
+        let link = "<https://api.github.com/repositories/1/releases?page=1>; rel=\"first\", \
+            <https://api.github.com/repositories/1/releases?page=4>; rel=\"prev\"";
+        assert_eq!(next_page(link), None);
+        assert_eq!(next_page_url(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn malformed_links_are_ignored() {
+        assert_eq!(
+            next_page("https://api.github.com/?page=2; rel=\"next\""),
+            None
+        );
+        assert_eq!(next_page("<not a url>; rel=\"next\""), None);
+        assert_eq!(next_page("<https://api.github.com/?page=2>"), None);
+    }
+}
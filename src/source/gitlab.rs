@@ -0,0 +1,322 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::{cache, config, source, Error, Result};
+
+pub async fn load<'a>(
+    client: &Client,
+    cache: cache::Entry<'a>,
+    project: &str,
+    filter: Filter,
+    transform: &config::Transform,
+) -> Result<cache::Reference> {
+    let latest_job = get_latest_job(client, project, &filter).await?;
+
+    if let Some(job) = latest_job {
+        use cache::UpdateResult::*;
+        match cache.try_update(cache::Token::ArtifactId(job.id)) {
+            Mismatch(updater) => {
+                let url = format!(
+                    "{}/jobs/{}/artifacts",
+                    project_url(&filter.instance, project),
+                    job.id
+                );
+                println!("downloading {}...", url);
+
+                let response = client.get(&url).await?;
+                let bytes = response.bytes().await?;
+                let file = source::File {
+                    name: format!("{}.zip", job.name),
+                    bytes,
+                };
+
+                if let Some(file) = transform.apply(file).await? {
+                    Ok(updater.update(file).await?)
+                } else {
+                    Err(Error::MissingArtifact)
+                }
+            }
+            Match(reference) => Ok(reference),
+        }
+    } else {
+        cache.get_existing().ok_or(Error::MissingArtifact)
+    }
+}
+
+pub async fn resolve(
+    client: &Client,
+    project: &str,
+    filter: Filter,
+) -> Result<Option<cache::Token>> {
+    let latest_job = get_latest_job(client, project, &filter).await?;
+    Ok(latest_job.map(|job| cache::Token::ArtifactId(job.id)))
+}
+
+/// Finds the newest successful job with artifacts among the latest successful pipelines.
+async fn get_latest_job(client: &Client, project: &str, filter: &Filter) -> Result<Option<Job>> {
+    let project_url = project_url(&filter.instance, project);
+
+    let mut query = vec![
+        ("status", "success"),
+        ("order_by", "id"),
+        ("sort", "desc"),
+        ("per_page", Filter::MAX_PIPELINES),
+    ];
+    if let Some(branch) = &filter.branch {
+        query.push(("ref", branch));
+    }
+    let url = format!("{}/pipelines", project_url);
+    let url = reqwest::Url::parse_with_params(&url, &query).expect("malformed gitlab url");
+    let pipelines: Vec<Pipeline> = client.get_json(url.as_str()).await?;
+
+    for pipeline in pipelines {
+        let url = format!(
+            "{}/pipelines/{}/jobs?scope[]=success&per_page=100",
+            project_url, pipeline.id
+        );
+        let mut jobs: Vec<Job> = client.get_json(&url).await?;
+        jobs.sort_by_key(|job| std::cmp::Reverse(job.id));
+
+        // jobs without an artifacts file never uploaded one, or their artifacts have expired
+        let job = jobs
+            .into_iter()
+            .filter(|job| filter.test_job(&job.name))
+            .find(|job| job.artifacts_file.is_some());
+        if job.is_some() {
+            return Ok(job);
+        }
+    }
+
+    Ok(None)
+}
+
+fn project_url(instance: &str, project: &str) -> String {
+    // projects can be referred to by their full path, which has to be url-encoded
+    format!(
+        "{}/api/v4/projects/{}",
+        instance.trim_end_matches('/'),
+        project.replace('/', "%2F")
+    )
+}
+
+#[derive(Clone, Debug)]
+pub struct Filter {
+    /// The base url of the GitLab instance, such as `https://gitlab.com`.
+    pub instance: String,
+    pub branch: Option<String>,
+    pub job: Option<String>,
+}
+
+impl Filter {
+    const MAX_PIPELINES: &'static str = "20";
+
+    #[inline]
+    pub fn test_job(&self, job: &str) -> bool {
+        self.job.as_ref().map(|r| r == job).unwrap_or(true)
+    }
+}
+
+#[derive(Clone)]
+pub struct Client {
+    client: Arc<reqwest::Client>,
+}
+
+impl Client {
+    pub fn new(token: Option<String>) -> Client {
+        let mut default_headers = reqwest::header::HeaderMap::new();
+
+        // unlike a `PRIVATE-TOKEN` header, authorization is not passed on when redirected to the artifact storage
+        if let Some(token) = token {
+            let authorization = format!("Bearer {}", token);
+            default_headers.insert(
+                reqwest::header::AUTHORIZATION,
+                authorization.parse().unwrap(),
+            );
+        }
+
+        let client = reqwest::Client::builder()
+            .gzip(true)
+            .user_agent("server-wrapper (https://github.com/NucleoidMC/server-wrapper)")
+            .default_headers(default_headers)
+            .build()
+            .unwrap();
+
+        Client {
+            client: Arc::new(client),
+        }
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let response = self.get(url).await?;
+        Ok(response.json().await?)
+    }
+
+    #[inline]
+    pub async fn get(&self, url: &str) -> Result<reqwest::Response> {
+        Ok(self.client.get(url).send().await?.error_for_status()?)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+struct Pipeline {
+    id: usize,
+    #[serde(rename = "ref")]
+    reference: String,
+    sha: String,
+    status: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+struct Job {
+    id: usize,
+    name: String,
+    status: String,
+    artifacts_file: Option<ArtifactsFile>,
+}
+
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+struct ArtifactsFile {
+    filename: String,
+    size: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::test_fixture::{seed, TempDir};
+    use crate::test_server::TestServer;
+
+    const PROJECT: &str = "/api/v4/projects/group%2Fproject";
+
+    fn job(id: usize, name: &str, artifacts: bool) -> Value {
+        let artifacts_file = artifacts.then(|| json!({ "filename": "artifacts.zip", "size": 4 }));
+        json!({ "id": id, "name": name, "status": "success", "artifacts_file": artifacts_file })
+    }
+
+    fn pipelines(ids: &[usize]) -> String {
+        let pipelines: Vec<Value> = ids
+            .iter()
+            .map(|id| json!({ "id": id, "ref": "main", "sha": "abc", "status": "success" }))
+            .collect();
+        Value::Array(pipelines).to_string()
+    }
+
+    fn filter(server: &TestServer, job: &str) -> Filter {
+        Filter {
+            instance: server.url.clone(),
+            branch: Some("main".to_owned()),
+            job: Some(job.to_owned()),
+        }
+    }
+
+    async fn server(jobs: &[(usize, Vec<Value>)]) -> TestServer {
+        let pipeline_ids: Vec<usize> = jobs.iter().map(|(id, _)| *id).collect();
+        let mut routes = vec![
+            (format!("{}/pipelines", PROJECT), pipelines(&pipeline_ids)),
+            (format!("{}/jobs/22/artifacts", PROJECT), "PK22".to_owned()),
+        ];
+        for (pipeline, jobs) in jobs {
+            routes.push((
+                format!("{}/pipelines/{}/jobs", PROJECT, pipeline),
+                Value::Array(jobs.clone()).to_string(),
+            ));
+        }
+
+        TestServer::start(
+            routes
+                .iter()
+                .map(|(path, body)| (path.as_str(), body.clone())),
+        )
+        .await
+    }
+
+    async fn load_job(
+        server: &TestServer,
+        loader: &mut cache::Loader,
+        job: &str,
+    ) -> Result<cache::Reference> {
+        load(
+            &Client::new(None),
+            loader.entry("mod"),
+            "group/project",
+            filter(server, job),
+            &config::Transform::Direct,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn newest_job_with_artifacts_is_loaded() {
+        let server = server(&[
+            (
+                2,
+                vec![
+                    job(21, "build", false),
+                    job(22, "build", true),
+                    job(23, "test", true),
+                ],
+            ),
+            (1, vec![job(11, "build", true)]),
+        ])
+        .await;
+        let client = Client::new(None);
+
+        let token = resolve(&client, "group/project", filter(&server, "build"))
+            .await
+            .unwrap();
+        assert!(matches!(token, Some(cache::Token::ArtifactId(22))));
+
+        let root = TempDir::new("gitlab-load");
+        let mut loader = cache::Loader::open(root.path()).await.unwrap();
+        let reference = load_job(&server, &mut loader, "build").await.unwrap();
+        assert_eq!(reference.read().await.unwrap(), b"PK22");
+
+        let query = &server.requests()[0];
+        assert!(query.contains("status=success") && query.contains("ref=main"));
+    }
+
+    #[tokio::test]
+    async fn older_pipelines_are_searched() {
+        let server = server(&[
+            (3, vec![job(31, "test", true)]),
+            (2, vec![job(21, "build", true)]),
+        ])
+        .await;
+
+        let token = resolve(
+            &Client::new(None),
+            "group/project",
+            filter(&server, "build"),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(token, Some(cache::Token::ArtifactId(21))));
+    }
+
+    #[tokio::test]
+    async fn missing_jobs_keep_the_existing_artifact() {
+        let server = server(&[(2, vec![job(21, "test", true), job(22, "build", false)])]).await;
+        let client = Client::new(None);
+
+        let token = resolve(&client, "group/project", filter(&server, "build"))
+            .await
+            .unwrap();
+        assert!(token.is_none());
+
+        let root = TempDir::new("gitlab-missing");
+        let mut loader = cache::Loader::open(root.path()).await.unwrap();
+        let result = load_job(&server, &mut loader, "build").await;
+        assert!(matches!(result, Err(Error::MissingArtifact)));
+
+        seed(&mut loader, "mod", cache::Token::ArtifactId(11), "PK11").await;
+
+        let reference = load_job(&server, &mut loader, "build").await.unwrap();
+        assert_eq!(reference.read().await.unwrap(), b"PK11");
+    }
+}
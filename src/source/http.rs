@@ -1,3 +1,6 @@
+use reqwest::header::{self, HeaderMap};
+use reqwest::StatusCode;
+
 use crate::{cache, config, source, Error, Result};
 
 pub async fn load<'a>(
@@ -6,18 +9,22 @@ pub async fn load<'a>(
     url: &str,
     transform: &config::Transform,
 ) -> Result<cache::Reference> {
-    let response = client.get(url).send().await?;
-
-    let etag = response
-        .headers()
-        .get(reqwest::header::ETAG)
-        .and_then(|etag| etag.to_str().ok());
+    let response = send(client, url, cache.current_token()).await?;
+    let response = if response.status() == StatusCode::NOT_MODIFIED {
+        if cache.is_cached() {
+            println!("{} was not modified", url);
+            return cache.get_existing().ok_or(Error::MissingArtifact);
+        }
 
-    let cache_token = match etag {
-        Some(etag) => cache::Token::Etag(etag[1..etag.len() - 1].to_owned()),
-        None => cache::Token::Unknown,
+        // the cached file has gone missing since, so it has to be downloaded again
+        send(client, url, &cache::Token::Unknown).await?
+    } else {
+        response
     };
 
+    let response = response.error_for_status()?;
+    let cache_token = token(response.headers()).unwrap_or(cache::Token::Unknown);
+
     use cache::UpdateResult::*;
     match cache.try_update(cache_token) {
         Mismatch(updater) => {
@@ -37,9 +44,185 @@ pub async fn load<'a>(
     }
 }
 
+/// Lets the server tell us when the version of the given token is still current, so that nothing has to be downloaded.
+async fn send(
+    client: &reqwest::Client,
+    url: &str,
+    token: &cache::Token,
+) -> Result<reqwest::Response> {
+    let mut request = client.get(url);
+    match token {
+        cache::Token::Etag(etag) => {
+            request = request.header(header::IF_NONE_MATCH, entity_tag(etag))
+        }
+        cache::Token::LastModified(last_modified) => {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified)
+        }
+        _ => (),
+    }
+
+    Ok(request.send().await?)
+}
+
+pub async fn resolve(client: &reqwest::Client, url: &str) -> Result<Option<cache::Token>> {
+    let response = client.head(url).send().await?;
+    Ok(token(response.headers()))
+}
+
+/// Prefers the ETag of a response, falling back to its modification date.
+fn token(headers: &HeaderMap) -> Option<cache::Token> {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+
+    if let Some(etag) = header(header::ETAG) {
+        Some(cache::Token::Etag(etag_token(etag)))
+    } else {
+        header(header::LAST_MODIFIED)
+            .map(|last_modified| cache::Token::LastModified(last_modified.to_owned()))
+    }
+}
+
+/// Strips the quotes of an entity tag such as `"abc"` or `W/"abc"`, keeping the weak prefix.
+fn etag_token(etag: &str) -> String {
+    let (weak, tag) = match etag.strip_prefix("W/") {
+        Some(tag) => ("W/", tag),
+        None => ("", etag),
+    };
+
+    let tag = tag
+        .strip_prefix('"')
+        .and_then(|tag| tag.strip_suffix('"'))
+        .unwrap_or(tag);
+
+    format!("{}{}", weak, tag)
+}
+
+/// Restores the entity tag that was stripped by `etag_token`.
+fn entity_tag(token: &str) -> String {
+    match token.strip_prefix("W/") {
+        Some(tag) => format!("W/\"{}\"", tag),
+        None => format!("\"{}\"", token),
+    }
+}
+
 fn file_name(url: &str) -> &str {
     match url.rsplit_once("/") {
         Some((_, name)) => name,
         None => url,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use bytes::Bytes;
+    use http_body_util::Full;
+    use hyper::Response;
+
+    use super::*;
+    use crate::test_fixture::TempDir;
+    use crate::test_server::TestServer;
+
+    /// Serves `contents` with the given ETag, recording the `If-None-Match` header of every request.
+    async fn server(
+        etag: &'static str,
+        contents: &'static str,
+    ) -> (TestServer, Arc<Mutex<Vec<Option<String>>>>) {
+        let conditions = Arc::new(Mutex::new(Vec::new()));
+        let server = TestServer::with_handler({
+            let conditions = conditions.clone();
+            move |request| {
+                let condition = request
+                    .headers()
+                    .get(header::IF_NONE_MATCH)
+                    .map(|value| value.to_str().unwrap().to_owned());
+                conditions.lock().unwrap().push(condition.clone());
+
+                let mut response =
+                    Response::new(Full::new(Bytes::from_static(contents.as_bytes())));
+                response
+                    .headers_mut()
+                    .insert(header::ETAG, etag.parse().unwrap());
+                if condition.is_some_and(|condition| condition == entity_tag(&etag_token(etag))) {
+                    *response.status_mut() = StatusCode::NOT_MODIFIED;
+                    *response.body_mut() = Full::new(Bytes::new());
+                }
+                response
+            }
+        })
+        .await;
+
+        (server, conditions)
+    }
+
+    async fn load_file(server: &TestServer, loader: &mut cache::Loader) -> cache::Reference {
+        let url = format!("{}/mod.jar", server.url);
+        load(
+            &reqwest::Client::new(),
+            loader.entry("mod"),
+            &url,
+            &config::Transform::Direct,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[test]
+    fn entity_tags_round_trip() {
+        assert_eq!(etag_token("\"abc\""), "abc");
+        assert_eq!(etag_token("W/\"abc\""), "W/abc");
+        assert_eq!(etag_token("abc"), "abc");
+
+        assert_eq!(entity_tag("abc"), "\"abc\"");
+        assert_eq!(entity_tag("W/abc"), "W/\"abc\"");
+    }
+
+    async fn assert_not_modified(etag: &'static str, condition: &str) {
+        let (server, conditions) = server(etag, "v1").await;
+        let root = TempDir::new(&format!("http-{}", etag_token(etag).replace('/', "")));
+        let mut loader = cache::Loader::open(root.path()).await.unwrap();
+
+        let reference = load_file(&server, &mut loader).await;
+        assert_eq!(reference.read().await.unwrap(), b"v1");
+
+        let reference = load_file(&server, &mut loader).await;
+        assert!(!reference.changed());
+        assert_eq!(reference.read().await.unwrap(), b"v1");
+
+        assert_eq!(
+            *conditions.lock().unwrap(),
+            [None, Some(condition.to_owned())]
+        );
+    }
+
+    #[tokio::test]
+    async fn weak_etags_are_sent_back() {
+        assert_not_modified("W/\"weak\"", "W/\"weak\"").await;
+    }
+
+    #[tokio::test]
+    async fn unquoted_etags_are_sent_back_quoted() {
+        assert_not_modified("unquoted", "\"unquoted\"").await;
+    }
+
+    #[tokio::test]
+    async fn missing_cached_files_are_downloaded_again() {
+        let (server, conditions) = server("\"tag\"", "v1").await;
+        let root = TempDir::new("http-missing");
+        let mut loader = cache::Loader::open(root.path()).await.unwrap();
+
+        load_file(&server, &mut loader).await;
+        for file in std::fs::read_dir(root.path()).unwrap() {
+            std::fs::remove_file(file.unwrap().path()).unwrap();
+        }
+
+        let reference = load_file(&server, &mut loader).await;
+        assert_eq!(reference.read().await.unwrap(), b"v1");
+
+        // the server answers the conditional request with a 304, which is retried without the condition
+        assert_eq!(
+            *conditions.lock().unwrap(),
+            [None, Some("\"tag\"".to_owned()), None]
+        );
+    }
+}
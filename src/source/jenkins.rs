@@ -0,0 +1,171 @@
+use serde::Deserialize;
+
+use crate::{cache, config, source, Error, Result};
+
+pub async fn load<'a>(
+    client: &reqwest::Client,
+    cache: cache::Entry<'a>,
+    job: &str,
+    filter: Filter,
+    transform: &config::Transform,
+) -> Result<cache::Reference> {
+    let artifact = get_artifact(client, job, &filter).await?;
+
+    if let Some(artifact) = artifact {
+        use cache::UpdateResult::*;
+        match cache.try_update(artifact.token()) {
+            Mismatch(updater) => {
+                println!("downloading {}...", artifact.url);
+
+                let response = client.get(&artifact.url).send().await?.error_for_status()?;
+                let bytes = response.bytes().await?;
+                let file = source::File {
+                    name: artifact.file_name,
+                    bytes,
+                };
+
+                if let Some(file) = transform.apply(file).await? {
+                    Ok(updater.update(file).await?)
+                } else {
+                    Err(Error::MissingArtifact)
+                }
+            }
+            Match(reference) => Ok(reference),
+        }
+    } else {
+        cache.get_existing().ok_or(Error::MissingArtifact)
+    }
+}
+
+pub async fn resolve(
+    client: &reqwest::Client,
+    job: &str,
+    filter: Filter,
+) -> Result<Option<cache::Token>> {
+    let artifact = get_artifact(client, job, &filter).await?;
+    Ok(artifact.map(|artifact| artifact.token()))
+}
+
+/// Resolves the build through the Jenkins JSON API and finds the first of its artifacts matching the filter.
+async fn get_artifact(
+    client: &reqwest::Client,
+    job: &str,
+    filter: &Filter,
+) -> Result<Option<BuildArtifact>> {
+    let job = job.trim_end_matches('/');
+
+    // a build that does not exist, such as the last successful build of a job that never succeeded, is a 404
+    let url = format!(
+        "{}/{}/api/json?tree=number,artifacts[fileName,relativePath]",
+        job, filter.build
+    );
+    let response = client.get(&url).send().await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        eprintln!("Warning: {} has no build {}", job, filter.build);
+        return Ok(None);
+    }
+    let build: Build = response.error_for_status()?.json().await?;
+
+    let artifact = build
+        .artifacts
+        .into_iter()
+        .find(|artifact| filter.test_artifact(&artifact.file_name));
+
+    Ok(artifact.map(|artifact| {
+        // artifact paths may contain spaces or other characters that are not valid in a url
+        let path: Vec<String> = artifact
+            .relative_path
+            .split('/')
+            .map(source::encode_path_segment)
+            .collect();
+
+        BuildArtifact {
+            build: build.number,
+            url: format!("{}/{}/artifact/{}", job, build.number, path.join("/")),
+            file_name: artifact.file_name,
+            relative_path: artifact.relative_path,
+        }
+    }))
+}
+
+#[derive(Clone, Debug)]
+pub struct Filter {
+    /// A build number or a permalink such as `lastSuccessfulBuild` or `lastStableBuild`.
+    pub build: String,
+    pub artifact: Option<config::Pattern>,
+}
+
+impl Filter {
+    #[inline]
+    pub fn test_artifact(&self, artifact: &str) -> bool {
+        self.artifact
+            .as_ref()
+            .map(|pattern| pattern.matches(artifact))
+            .unwrap_or(true)
+    }
+}
+
+struct BuildArtifact {
+    build: u64,
+    url: String,
+    file_name: String,
+    relative_path: String,
+}
+
+impl BuildArtifact {
+    fn token(&self) -> cache::Token {
+        cache::Token::Build(self.build, self.relative_path.clone())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Build {
+    number: u64,
+    artifacts: Vec<Artifact>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Artifact {
+    file_name: String,
+    relative_path: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_server::TestServer;
+
+    #[tokio::test]
+    async fn artifact_paths_are_encoded() {
+        let build = json!({
+            "number": 7,
+            "artifacts": [{ "fileName": "my mod+1.jar", "relativePath": "build/libs/my mod+1.jar" }],
+        });
+        let server =
+            TestServer::start([("/job/mod/lastSuccessfulBuild/api/json", build.to_string())]).await;
+
+        let filter = Filter {
+            build: "lastSuccessfulBuild".to_owned(),
+            artifact: None,
+        };
+        let job = format!("{}/job/mod/", server.url);
+        let artifact = get_artifact(&reqwest::Client::new(), &job, &filter)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            artifact.url,
+            format!(
+                "{}/job/mod/7/artifact/build/libs/my%20mod%2B1.jar",
+                server.url
+            )
+        );
+        assert!(
+            matches!(artifact.token(), cache::Token::Build(7, path) if path == "build/libs/my mod+1.jar")
+        );
+    }
+}
@@ -0,0 +1,367 @@
+use sha1::Sha1;
+use sha2::{Digest, Sha512};
+
+use crate::{cache, config, source, Error, Result};
+
+use version::{Version, VersionRange};
+
+mod version;
+
+pub async fn load<'a>(
+    client: &reqwest::Client,
+    cache: cache::Entry<'a>,
+    repository: &str,
+    artifact: &str,
+    classifier: &Option<String>,
+    transform: &config::Transform,
+) -> Result<cache::Reference> {
+    let coordinates = Coordinates::parse(artifact)?;
+    let file = resolve_file(client, repository, &coordinates, classifier).await?;
+
+    if let Some(file) = file {
+        let token = match &file.checksum {
+            Some(checksum) => checksum.token(),
+            None => cache::Token::Unknown,
+        };
+
+        use cache::UpdateResult::*;
+        match cache.try_update(token) {
+            Mismatch(updater) => {
+                println!("downloading {}...", file.url);
+
+                let response = client.get(&file.url).send().await?.error_for_status()?;
+                let bytes = response.bytes().await?;
+
+                if let Some(checksum) = &file.checksum {
+                    if !checksum.verify(&bytes) {
+                        return Err(Error::ChecksumMismatch(file.url));
+                    }
+                }
+
+                let file = source::File {
+                    name: file.name,
+                    bytes,
+                };
+
+                if let Some(file) = transform.apply(file).await? {
+                    Ok(updater.update(file).await?)
+                } else {
+                    Err(Error::MissingArtifact)
+                }
+            }
+            Match(reference) => Ok(reference),
+        }
+    } else {
+        cache.get_existing().ok_or(Error::MissingArtifact)
+    }
+}
+
+pub async fn resolve(
+    client: &reqwest::Client,
+    repository: &str,
+    artifact: &str,
+    classifier: &Option<String>,
+) -> Result<Option<cache::Token>> {
+    let coordinates = Coordinates::parse(artifact)?;
+    let file = resolve_file(client, repository, &coordinates, classifier).await?;
+    Ok(file
+        .and_then(|file| file.checksum)
+        .map(|checksum| checksum.token()))
+}
+
+async fn resolve_file(
+    client: &reqwest::Client,
+    repository: &str,
+    coordinates: &Coordinates,
+    classifier: &Option<String>,
+) -> Result<Option<MavenFile>> {
+    let artifact_url = format!(
+        "{}/{}/{}",
+        repository.trim_end_matches('/'),
+        coordinates.group.replace('.', "/"),
+        coordinates.artifact
+    );
+
+    let version = match resolve_version(client, &artifact_url, &coordinates.version).await? {
+        Some(version) => version,
+        None => return Ok(None),
+    };
+
+    // snapshots are deployed with a timestamp in place of `SNAPSHOT` in their file names
+    let file_version = if version.ends_with("-SNAPSHOT") {
+        let url = format!("{}/{}/maven-metadata.xml", artifact_url, version);
+        let metadata = get_metadata(client, &url).await?;
+        metadata
+            .snapshot_version(&version, classifier)
+            .unwrap_or_else(|| version.clone())
+    } else {
+        version.clone()
+    };
+
+    let name = match classifier {
+        Some(classifier) => format!(
+            "{}-{}-{}.jar",
+            coordinates.artifact, file_version, classifier
+        ),
+        None => format!("{}-{}.jar", coordinates.artifact, file_version),
+    };
+    let url = format!("{}/{}/{}", artifact_url, version, name);
+
+    let checksum = get_checksum(client, &url).await?;
+    if checksum.is_none() {
+        eprintln!(
+            "Warning: {} has no published checksum, it will be downloaded every time",
+            url
+        );
+    }
+
+    Ok(Some(MavenFile {
+        name,
+        url,
+        checksum,
+    }))
+}
+
+async fn resolve_version(
+    client: &reqwest::Client,
+    artifact_url: &str,
+    version: &str,
+) -> Result<Option<String>> {
+    let range = if version == "latest" || version == "release" {
+        None
+    } else if version.starts_with('[') || version.starts_with('(') {
+        let range = VersionRange::parse(version)
+            .ok_or_else(|| Error::MalformedMavenReference(version.to_owned()))?;
+        Some(range)
+    } else {
+        return Ok(Some(version.to_owned()));
+    };
+
+    let url = format!("{}/maven-metadata.xml", artifact_url);
+    let metadata = get_metadata(client, &url).await?;
+
+    let version = match range {
+        Some(range) => metadata
+            .versions
+            .iter()
+            .filter(|version| range.contains(&Version::parse(version)))
+            .max_by(|a, b| Version::parse(a).cmp(&Version::parse(b)))
+            .cloned(),
+        None if version == "latest" => metadata
+            .latest
+            .or_else(|| metadata.versions.last().cloned()),
+        None => metadata.release.or_else(|| {
+            metadata
+                .versions
+                .iter()
+                .rev()
+                .find(|version| !version.ends_with("-SNAPSHOT"))
+                .cloned()
+        }),
+    };
+
+    Ok(version)
+}
+
+async fn get_metadata(client: &reqwest::Client, url: &str) -> Result<Metadata> {
+    let response = client.get(url).send().await?.error_for_status()?;
+    let xml = response.text().await?;
+    Metadata::parse(&xml).ok_or_else(|| Error::MalformedMavenMetadata(url.to_owned()))
+}
+
+/// Fetches the strongest checksum published alongside the file at the given url, if any.
+async fn get_checksum(client: &reqwest::Client, url: &str) -> Result<Option<Checksum>> {
+    for extension in ["sha512", "sha1"] {
+        let response = client.get(format!("{}.{}", url, extension)).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            continue;
+        }
+
+        let text = response.error_for_status()?.text().await?;
+
+        // checksum files may be followed by the name of the file they belong to
+        let hex = text.split_whitespace().next().unwrap_or("").to_lowercase();
+        let checksum = match extension {
+            "sha512" => Checksum::parse_sha512(&hex),
+            _ => Checksum::parse_sha1(&hex),
+        };
+
+        match checksum {
+            Some(checksum) => return Ok(Some(checksum)),
+            None => eprintln!("Warning: malformed checksum at {}.{}", url, extension),
+        }
+    }
+
+    Ok(None)
+}
+
+struct MavenFile {
+    name: String,
+    url: String,
+    checksum: Option<Checksum>,
+}
+
+/// Maven coordinates in the form of `group:artifact[:version]`, where the version defaults to `release`.
+struct Coordinates {
+    group: String,
+    artifact: String,
+    version: String,
+}
+
+impl Coordinates {
+    fn parse(coordinates: &str) -> Result<Coordinates> {
+        let malformed = || Error::MalformedMavenReference(coordinates.to_owned());
+
+        let (group, artifact, version) =
+            match coordinates.split(':').collect::<Vec<&str>>().as_slice() {
+                [group, artifact] => (*group, *artifact, "release"),
+                [group, artifact, version] => (*group, *artifact, *version),
+                _ => return Err(malformed()),
+            };
+
+        if group.is_empty() || artifact.is_empty() || version.is_empty() {
+            return Err(malformed());
+        }
+
+        Ok(Coordinates {
+            group: group.to_owned(),
+            artifact: artifact.to_owned(),
+            version: version.to_owned(),
+        })
+    }
+}
+
+enum Checksum {
+    Sha1([u8; 20]),
+    Sha512(String),
+}
+
+impl Checksum {
+    fn parse_sha1(hex: &str) -> Option<Checksum> {
+        let mut hash = [0u8; 20];
+        hex::decode_to_slice(hex, &mut hash).ok()?;
+        Some(Checksum::Sha1(hash))
+    }
+
+    fn parse_sha512(hex: &str) -> Option<Checksum> {
+        let mut hash = [0u8; 64];
+        hex::decode_to_slice(hex, &mut hash).ok()?;
+        Some(Checksum::Sha512(hex.to_owned()))
+    }
+
+    fn verify(&self, bytes: &[u8]) -> bool {
+        match self {
+            Checksum::Sha1(hash) => Sha1::digest(bytes).as_slice() == hash,
+            Checksum::Sha512(hash) => hex::encode(Sha512::digest(bytes)) == *hash,
+        }
+    }
+
+    fn token(&self) -> cache::Token {
+        match self {
+            Checksum::Sha1(hash) => cache::Token::Sha1(*hash),
+            Checksum::Sha512(hash) => cache::Token::Sha512(hash.clone()),
+        }
+    }
+}
+
+struct Metadata {
+    latest: Option<String>,
+    release: Option<String>,
+    versions: Vec<String>,
+    snapshot: Option<String>,
+    snapshot_versions: Vec<SnapshotVersion>,
+}
+
+struct SnapshotVersion {
+    classifier: Option<String>,
+    extension: String,
+    value: String,
+}
+
+impl Metadata {
+    fn parse(xml: &str) -> Option<Metadata> {
+        let document = roxmltree::Document::parse(xml).ok()?;
+        let root = document.root_element();
+
+        let versioning = match child(root, "versioning") {
+            Some(versioning) => versioning,
+            None => {
+                return Some(Metadata {
+                    latest: None,
+                    release: None,
+                    versions: Vec::new(),
+                    snapshot: None,
+                    snapshot_versions: Vec::new(),
+                })
+            }
+        };
+
+        let versions = child(versioning, "versions")
+            .map(|versions| {
+                versions
+                    .children()
+                    .filter(|version| version.has_tag_name("version"))
+                    .filter_map(|version| text(Some(version)))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let snapshot = child(versioning, "snapshot").and_then(|snapshot| {
+            let timestamp = text(child(snapshot, "timestamp"))?;
+            let build_number = text(child(snapshot, "buildNumber"))?;
+            Some(format!("{}-{}", timestamp, build_number))
+        });
+
+        let snapshot_versions = child(versioning, "snapshotVersions")
+            .map(|snapshot_versions| {
+                snapshot_versions
+                    .children()
+                    .filter(|version| version.has_tag_name("snapshotVersion"))
+                    .filter_map(|version| {
+                        Some(SnapshotVersion {
+                            classifier: text(child(version, "classifier")),
+                            extension: text(child(version, "extension"))?,
+                            value: text(child(version, "value"))?,
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Some(Metadata {
+            latest: text(child(versioning, "latest")),
+            release: text(child(versioning, "release")),
+            versions,
+            snapshot,
+            snapshot_versions,
+        })
+    }
+
+    /// Finds the timestamped version of the jar with the given classifier within snapshot metadata.
+    fn snapshot_version(&self, version: &str, classifier: &Option<String>) -> Option<String> {
+        let snapshot_version = self
+            .snapshot_versions
+            .iter()
+            .find(|snapshot| snapshot.extension == "jar" && &snapshot.classifier == classifier);
+
+        match snapshot_version {
+            Some(snapshot_version) => Some(snapshot_version.value.clone()),
+            None => {
+                let snapshot = self.snapshot.as_ref()?;
+                Some(version.replace("SNAPSHOT", snapshot))
+            }
+        }
+    }
+}
+
+fn child<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn text(node: Option<roxmltree::Node>) -> Option<String> {
+    node.and_then(|node| node.text())
+        .map(|text| text.trim().to_owned())
+}
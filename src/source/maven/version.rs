@@ -0,0 +1,319 @@
+use std::cmp::Ordering;
+
+/// A version compared by its numeric and qualifier components, approximating Maven's version ordering.
+#[derive(Debug, Clone)]
+pub struct Version {
+    items: Vec<Item>,
+    /// Build metadata following a `+`, such as the Minecraft version in `0.5.1+1.20.1`.
+    build: Option<Box<Version>>,
+}
+
+#[derive(Debug, Clone)]
+enum Item {
+    Number(u64),
+    Qualifier(String),
+}
+
+impl Version {
+    pub fn parse(version: &str) -> Version {
+        let (version, build) = match version.split_once('+') {
+            Some((version, build)) => (version, Some(Box::new(Version::parse(build)))),
+            None => (version, None),
+        };
+
+        let mut items = Vec::new();
+
+        for part in version.split(['.', '-', '_']) {
+            // split further where digits and letters meet, such as in `1.0rc1`
+            let mut start = 0;
+            let chars: Vec<(usize, char)> = part.char_indices().collect();
+            for window in chars.windows(2) {
+                let ((_, a), (index, b)) = (window[0], window[1]);
+                if a.is_ascii_digit() != b.is_ascii_digit() {
+                    items.push(Item::parse(&part[start..index]));
+                    start = index;
+                }
+            }
+
+            if start < part.len() {
+                items.push(Item::parse(&part[start..]));
+            }
+        }
+
+        Version { items, build }
+    }
+}
+
+impl Item {
+    fn parse(item: &str) -> Item {
+        match item.parse::<u64>() {
+            Ok(number) => Item::Number(number),
+            Err(_) => Item::Qualifier(item.to_lowercase()),
+        }
+    }
+}
+
+const UNKNOWN_QUALIFIER_RANK: u8 = 7;
+
+fn qualifier_rank(qualifier: &str) -> u8 {
+    match qualifier {
+        "alpha" | "a" => 0,
+        "beta" | "b" => 1,
+        "milestone" | "m" => 2,
+        "rc" | "cr" | "pre" => 3,
+        "snapshot" => 4,
+        "" | "ga" | "final" | "release" => 5,
+        "sp" => 6,
+        _ => UNKNOWN_QUALIFIER_RANK,
+    }
+}
+
+fn compare_qualifiers(left: &str, right: &str) -> Ordering {
+    let (left_rank, right_rank) = (qualifier_rank(left), qualifier_rank(right));
+
+    // aliases such as `final` and `ga` are equal, while unknown qualifiers are compared lexically
+    if left_rank == UNKNOWN_QUALIFIER_RANK && right_rank == UNKNOWN_QUALIFIER_RANK {
+        left.cmp(right)
+    } else {
+        left_rank.cmp(&right_rank)
+    }
+}
+
+fn compare_items(left: Option<&Item>, right: Option<&Item>) -> Ordering {
+    use Item::*;
+
+    // missing items are padded with whatever is neutral to the other side: zero or a release qualifier
+    match (left, right) {
+        (Some(Number(left)), Some(Number(right))) => left.cmp(right),
+        (Some(Number(_)), Some(Qualifier(_))) => Ordering::Greater,
+        (Some(Qualifier(_)), Some(Number(_))) => Ordering::Less,
+        (Some(Qualifier(left)), Some(Qualifier(right))) => compare_qualifiers(left, right),
+        (Some(Number(left)), None) => left.cmp(&0),
+        (None, Some(Number(right))) => 0.cmp(right),
+        (Some(Qualifier(left)), None) => compare_qualifiers(left, ""),
+        (None, Some(Qualifier(right))) => compare_qualifiers("", right),
+        (None, None) => Ordering::Equal,
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Version) -> Ordering {
+        let len = self.items.len().max(other.items.len());
+        (0..len)
+            .map(|index| compare_items(self.items.get(index), other.items.get(index)))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+            // build metadata only decides between otherwise equal versions
+            .then_with(|| self.build.cmp(&other.build))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Version) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Version) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Version {}
+
+/// A Maven version range such as `[1.0,2.0)`, `[1.5,)` or a union like `(,1.0],[1.2,)`.
+#[derive(Debug, Clone)]
+pub struct VersionRange {
+    restrictions: Vec<Restriction>,
+}
+
+#[derive(Debug, Clone)]
+struct Restriction {
+    lower: Option<Bound>,
+    upper: Option<Bound>,
+}
+
+#[derive(Debug, Clone)]
+struct Bound {
+    version: Version,
+    inclusive: bool,
+}
+
+impl VersionRange {
+    pub fn parse(range: &str) -> Option<VersionRange> {
+        let mut restrictions = Vec::new();
+
+        let mut rest = range.trim();
+        while !rest.is_empty() {
+            let lower_inclusive = match rest.chars().next()? {
+                '[' => true,
+                '(' => false,
+                _ => return None,
+            };
+
+            let end = rest.find([']', ')'])?;
+            let upper_inclusive = rest[end..].starts_with(']');
+            let inner = &rest[1..end];
+
+            let restriction = match inner.split_once(',') {
+                Some((lower, upper)) => Restriction {
+                    lower: Bound::parse(lower, lower_inclusive),
+                    upper: Bound::parse(upper, upper_inclusive),
+                },
+                None => {
+                    // a single version in brackets pins exactly that version
+                    if !lower_inclusive || !upper_inclusive {
+                        return None;
+                    }
+                    let bound = Bound::parse(inner, true)?;
+                    Restriction {
+                        lower: Some(bound.clone()),
+                        upper: Some(bound),
+                    }
+                }
+            };
+            restrictions.push(restriction);
+
+            rest = rest[end + 1..].trim_start();
+            rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+        }
+
+        if restrictions.is_empty() {
+            None
+        } else {
+            Some(VersionRange { restrictions })
+        }
+    }
+
+    pub fn contains(&self, version: &Version) -> bool {
+        self.restrictions
+            .iter()
+            .any(|restriction| restriction.contains(version))
+    }
+}
+
+impl Restriction {
+    fn contains(&self, version: &Version) -> bool {
+        let above_lower = match &self.lower {
+            Some(lower) if lower.inclusive => version >= &lower.version,
+            Some(lower) => version > &lower.version,
+            None => true,
+        };
+        let below_upper = match &self.upper {
+            Some(upper) if upper.inclusive => version <= &upper.version,
+            Some(upper) => version < &upper.version,
+            None => true,
+        };
+        above_lower && below_upper
+    }
+}
+
+impl Bound {
+    fn parse(version: &str, inclusive: bool) -> Option<Bound> {
+        let version = version.trim();
+        if version.is_empty() {
+            None
+        } else {
+            Some(Bound {
+                version: Version::parse(version),
+                inclusive,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_ascending(versions: &[&str]) {
+        for pair in versions.windows(2) {
+            let (lower, higher) = (Version::parse(pair[0]), Version::parse(pair[1]));
+            assert!(lower < higher, "expected {} < {}", pair[0], pair[1]);
+        }
+    }
+
+    fn range_contains(range: &str, version: &str) -> bool {
+        VersionRange::parse(range)
+            .unwrap_or_else(|| panic!("malformed range {}", range))
+            .contains(&Version::parse(version))
+    }
+
+    #[test]
+    fn versions_order_numerically() {
+        assert_ascending(&["0.9", "0.10", "1", "1.0.1", "1.1", "1.10", "2"]);
+        assert_eq!(Version::parse("1"), Version::parse("1.0.0"));
+    }
+
+    #[test]
+    fn qualifiers_order_before_releases() {
+        assert_ascending(&[
+            "1.0-alpha",
+            "1.0-alpha2",
+            "1.0-beta1",
+            "1.0-m1",
+            "1.0-rc1",
+            "1.0-rc2",
+            "1.0-SNAPSHOT",
+            "1.0",
+            "1.0-sp1",
+            "1.0.1",
+        ]);
+        assert_eq!(Version::parse("1.0rc1"), Version::parse("1.0-rc-1"));
+        assert_eq!(Version::parse("1.0-final"), Version::parse("1.0"));
+        assert_ascending(&["1.0-sp1", "1.0-dev", "1.0-zeta"]);
+    }
+
+    #[test]
+    fn build_metadata_is_compared_last() {
+        assert_ascending(&["0.5+1.20.1", "0.5.1+1.20.1", "0.6+1.19.4"]);
+        assert_ascending(&["0.5", "0.5+1.20.1", "0.5+1.20.2", "0.5+1.21"]);
+        assert_ascending(&["0.5-beta+1.21", "0.5+1.20.1"]);
+    }
+
+    #[test]
+    fn ranges_with_two_bounds() {
+        assert!(range_contains("[1.0,2.0)", "1.0"));
+        assert!(range_contains("[1.0,2.0)", "1.9.9"));
+        assert!(!range_contains("[1.0,2.0)", "2.0"));
+        assert!(!range_contains("[1.0,2.0)", "0.9"));
+
+        assert!(!range_contains("(1.0,2.0]", "1.0"));
+        assert!(range_contains("(1.0,2.0]", "2.0"));
+
+        // build metadata does not take a version out of its range
+        assert!(range_contains("[0.5,0.6)", "0.5.1+1.20.1"));
+        assert!(!range_contains("[0.5,0.6)", "0.6+1.20.1"));
+    }
+
+    #[test]
+    fn unbounded_ranges() {
+        assert!(range_contains("(,1.0]", "0.1"));
+        assert!(range_contains("(,1.0]", "1.0"));
+        assert!(!range_contains("(,1.0]", "1.0.1"));
+
+        assert!(range_contains("[1.5,)", "1.5"));
+        assert!(range_contains("[1.5,)", "100"));
+        assert!(!range_contains("[1.5,)", "1.4"));
+    }
+
+    #[test]
+    fn exact_and_union_ranges() {
+        assert!(range_contains("[1.5]", "1.5"));
+        assert!(range_contains("[1.5]", "1.5.0"));
+        assert!(!range_contains("[1.5]", "1.5.1"));
+
+        assert!(range_contains("(,1.0],[1.2,)", "0.5"));
+        assert!(!range_contains("(,1.0],[1.2,)", "1.1"));
+        assert!(range_contains("(,1.0],[1.2,)", "1.2"));
+    }
+
+    #[test]
+    fn malformed_ranges() {
+        assert!(VersionRange::parse("").is_none());
+        assert!(VersionRange::parse("1.0").is_none());
+        assert!(VersionRange::parse("[1.0").is_none());
+        assert!(VersionRange::parse("(1.5)").is_none());
+    }
+}
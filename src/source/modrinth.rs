@@ -1,3 +1,4 @@
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 
 use chrono::DateTime;
@@ -10,17 +11,51 @@ pub async fn load<'a>(
     client: &Client,
     cache: cache::Entry<'a>,
     project_id: &str,
-    game_version: &Option<String>,
+    filter: Filter,
     transform: &config::Transform,
 ) -> Result<cache::Reference> {
-    let latest_version = resolve_version(client, project_id, game_version).await?;
-    if let Some((hash, url, name)) = latest_version {
+    let latest_version = resolve_version(client, project_id, &filter).await?;
+    load_version(client, cache, latest_version.as_ref(), transform).await
+}
+
+pub async fn resolve(
+    client: &Client,
+    project_id: &str,
+    filter: Filter,
+) -> Result<Option<cache::Token>> {
+    let latest_version = resolve_version(client, project_id, &filter).await?;
+    Ok(latest_version
+        .as_ref()
+        .and_then(|version| version.primary_file())
+        .map(|file| file.token()))
+}
+
+/// Loads a dependency that was found through [`resolve_dependencies`].
+pub async fn load_dependency<'a>(
+    client: &Client,
+    cache: cache::Entry<'a>,
+    dependency: &Dependency,
+) -> Result<cache::Reference> {
+    let transform = config::Transform::Direct;
+    load_version(client, cache, Some(&dependency.version), &transform).await
+}
+
+async fn load_version<'a>(
+    client: &Client,
+    cache: cache::Entry<'a>,
+    version: Option<&ProjectVersion>,
+    transform: &config::Transform,
+) -> Result<cache::Reference> {
+    if let Some(file) = version.and_then(|version| version.primary_file()) {
         use cache::UpdateResult::*;
-        match cache.try_update(cache::Token::Sha512(hash)) {
+        match cache.try_update(file.token()) {
             Mismatch(updater) => {
-                let response = client.get(&url).await?;
+                let response = client.get(&file.url).await?;
                 let bytes = response.bytes().await?;
-                let file = source::File { name, bytes };
+                let file = source::File {
+                    name: file.filename.clone(),
+                    bytes,
+                };
 
                 if let Some(file) = transform.apply(file).await? {
                     Ok(updater.update(file).await?)
@@ -38,33 +73,288 @@ pub async fn load<'a>(
 async fn resolve_version(
     client: &Client,
     project_id: &str,
-    game_version: &Option<String>,
-) -> Result<Option<(String, String, String)>> {
-    let mut versions = client.get_versions(project_id, game_version).await?;
+    filter: &Filter,
+) -> Result<Option<ProjectVersion>> {
+    let versions = match &filter.version {
+        Some(version) => vec![client.get_version(project_id, version).await?],
+        None => {
+            let mut versions = client
+                .get_versions(project_id, &filter.compatibility())
+                .await?;
+            versions.retain(|version| filter.test_channel(&version.version_type));
+            versions
+        }
+    };
+
+    Ok(latest_version(versions))
+}
+
+fn latest_version(mut versions: Vec<ProjectVersion>) -> Option<ProjectVersion> {
     versions.sort_by_key(|v| v.date_published);
     // try latest versions first
     versions.reverse();
     for version in versions {
-        let file = version.files.iter().filter(|f| f.primary).next();
+        let file = version.files.iter().find(|f| f.primary);
         if let Some(file) = file {
-            if let Some(hash) = &file.hashes.sha512 {
-                return Ok(Some((
-                    hash.clone(),
-                    file.url.clone(),
-                    file.filename.clone(),
-                )));
+            if file.hashes.sha512.is_some() {
+                return Some(version);
             } else {
                 eprintln!("Warning: encountered old mod version without sha512 hash, skipping");
             }
         }
     }
 
-    return Ok(None);
+    None
+}
+
+/// A Modrinth source of a destination that dependencies may be resolved for.
+pub struct Root<'a> {
+    pub key: &'a str,
+    pub project_id: &'a str,
+    pub filter: Filter,
+    /// Whether the required dependencies of this source should be loaded.
+    pub dependencies: bool,
+}
+
+/// A required dependency that is not provided by any source of a destination.
+pub struct Dependency {
+    /// The slug of the dependency's project, used as its cache key.
+    pub key: String,
+    version: ProjectVersion,
+}
+
+pub struct Dependencies {
+    pub resolved: Vec<Dependency>,
+    /// Conflicting or missing dependencies that should be reported before the server starts.
+    pub problems: Vec<String>,
+}
+
+/// Recursively resolves the required dependencies of the given sources for the same game versions and loaders.
+/// Dependencies are skipped when their project is already provided by a source: either by a Modrinth source of
+/// the same project, or by any source whose key is the slug of the project.
+pub async fn resolve_dependencies(
+    client: &Client,
+    roots: Vec<Root<'_>>,
+    keys: &HashSet<String>,
+) -> Result<Dependencies> {
+    // maps the project ids that are provided to the id of their version, if known, and the key of the source providing them
+    let mut provided: HashMap<String, (Option<String>, String)> = HashMap::new();
+    let mut queue = VecDeque::new();
+
+    let mut resolved = Vec::new();
+    let mut problems = Vec::new();
+    let mut incompatible = Vec::new();
+
+    // a failed lookup only affects the dependencies that needed it, so it is reported along with other problems
+    for root in roots {
+        let version = match resolve_version(client, root.project_id, &root.filter).await {
+            Ok(Some(version)) => version,
+            Ok(None) => continue,
+            Err(err) => {
+                problems.push(format!(
+                    "failed to resolve dependencies of `{}`: {}",
+                    root.key, err
+                ));
+                continue;
+            }
+        };
+
+        provided.insert(
+            version.project_id.clone(),
+            (Some(version.id.clone()), root.key.to_owned()),
+        );
+
+        if root.dependencies {
+            let compatibility = root.filter.compatibility_with(&version);
+            for dependency in &version.dependencies {
+                queue.push_back((
+                    root.key.to_owned(),
+                    dependency.clone(),
+                    compatibility.clone(),
+                ));
+            }
+        }
+    }
+
+    while let Some((dependent, dependency, compatibility)) = queue.pop_front() {
+        match dependency.dependency_type.as_str() {
+            "required" => (),
+            "incompatible" => {
+                incompatible.push((dependent, dependency));
+                continue;
+            }
+            _ => continue,
+        }
+
+        // dependencies may reference only a specific version, in which case we need it to find its project
+        let pinned_version = match (&dependency.project_id, &dependency.version_id) {
+            (None, Some(version_id)) => match client.get_version_by_id(version_id).await {
+                Ok(version) => Some(version),
+                Err(err) => {
+                    problems.push(lookup_failed(&dependent, version_id, err));
+                    continue;
+                }
+            },
+            _ => None,
+        };
+        let project_id = match (&dependency.project_id, &pinned_version) {
+            (Some(project_id), _) => project_id.clone(),
+            (None, Some(version)) => version.project_id.clone(),
+            (None, None) => {
+                let file_name = dependency.file_name.as_deref().unwrap_or("an unknown file");
+                problems.push(format!(
+                    "`{}` requires `{}`, which is not available on Modrinth",
+                    dependent, file_name
+                ));
+                continue;
+            }
+        };
+
+        if let Some((version_id, provider)) = provided.get(&project_id) {
+            // the version is unknown when the project is provided by a source of another type
+            let conflicting = match (&dependency.version_id, version_id) {
+                (Some(required_version), Some(version_id)) => required_version != version_id,
+                _ => false,
+            };
+            if conflicting {
+                problems.push(format!(
+                    "`{}` requires a different version of `{}` than the one that is loaded",
+                    dependent, provider
+                ));
+            }
+            continue;
+        }
+
+        let project = match client.get_project(&project_id).await {
+            Ok(project) => project,
+            Err(err) => {
+                problems.push(lookup_failed(&dependent, &project_id, err));
+                continue;
+            }
+        };
+        if keys.contains(&project.slug) {
+            provided.insert(project_id, (None, project.slug));
+            continue;
+        }
+
+        let version = match (pinned_version, &dependency.version_id) {
+            (Some(version), _) => Ok(Some(version)),
+            (None, Some(version_id)) => client.get_version_by_id(version_id).await.map(Some),
+            (None, None) => client
+                .get_versions(&project_id, &compatibility)
+                .await
+                .map(latest_version),
+        };
+
+        let version = match version {
+            Ok(Some(version)) => version,
+            Err(err) => {
+                problems.push(lookup_failed(&dependent, &project.slug, err));
+                continue;
+            }
+            Ok(None) => {
+                problems.push(format!(
+                    "`{}` requires `{}`, but no compatible version was found",
+                    dependent, project.slug
+                ));
+                continue;
+            }
+        };
+
+        println!(
+            "[{}] resolved as a dependency of {}",
+            project.slug, dependent
+        );
+
+        provided.insert(project_id, (Some(version.id.clone()), project.slug.clone()));
+        for dependency in &version.dependencies {
+            queue.push_back((
+                project.slug.clone(),
+                dependency.clone(),
+                compatibility.clone(),
+            ));
+        }
+
+        resolved.push(Dependency {
+            key: project.slug,
+            version,
+        });
+    }
+
+    for (dependent, dependency) in incompatible {
+        let provider = dependency
+            .project_id
+            .as_ref()
+            .and_then(|project_id| provided.get(project_id));
+        if let Some((_, provider)) = provider {
+            problems.push(format!(
+                "`{}` is incompatible with `{}`",
+                dependent, provider
+            ));
+        }
+    }
+
+    Ok(Dependencies { resolved, problems })
+}
+
+fn lookup_failed(dependent: &str, dependency: &str, err: Error) -> String {
+    format!(
+        "`{}` requires `{}`, but it could not be looked up: {}",
+        dependent, dependency, err
+    )
+}
+
+#[derive(Clone, Debug)]
+pub struct Filter {
+    pub game_version: Option<String>,
+    pub loaders: Vec<String>,
+    /// A specific version number or id to load instead of the latest version.
+    pub version: Option<String>,
+    pub channel: Option<config::Channel>,
+}
+
+impl Filter {
+    fn compatibility(&self) -> Compatibility {
+        Compatibility {
+            game_versions: self.game_version.iter().cloned().collect(),
+            loaders: self.loaders.clone(),
+        }
+    }
+
+    /// Dependencies need to match the game version and loaders of the source, falling back to those
+    /// supported by its loaded version when not configured.
+    fn compatibility_with(&self, version: &ProjectVersion) -> Compatibility {
+        let mut compatibility = self.compatibility();
+        if compatibility.game_versions.is_empty() {
+            compatibility.game_versions = version.game_versions.clone();
+        }
+        if compatibility.loaders.is_empty() {
+            compatibility.loaders = version.loaders.clone();
+        }
+        compatibility
+    }
+
+    #[inline]
+    pub fn test_channel(&self, version_type: &str) -> bool {
+        let channel = match version_type {
+            "release" => config::Channel::Release,
+            "beta" => config::Channel::Beta,
+            _ => config::Channel::Alpha,
+        };
+        self.channel.map(|r| channel >= r).unwrap_or(true)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Compatibility {
+    game_versions: Vec<String>,
+    loaders: Vec<String>,
 }
 
 #[derive(Clone)]
 pub struct Client {
     client: Arc<reqwest::Client>,
+    base_url: String,
 }
 
 impl Client {
@@ -73,25 +363,62 @@ impl Client {
     pub fn new(client: reqwest::Client) -> Client {
         Client {
             client: Arc::new(client),
+            base_url: Client::BASE_URL.to_owned(),
+        }
+    }
+
+    #[cfg(test)]
+    fn with_base_url(client: reqwest::Client, base_url: String) -> Client {
+        Client {
+            client: Arc::new(client),
+            base_url,
         }
     }
 
     async fn get_versions(
         &self,
         project_id: &str,
-        game_version: &Option<String>,
+        compatibility: &Compatibility,
     ) -> Result<Vec<ProjectVersion>> {
-        let url = if let Some(game_version) = game_version {
-            format!(
-                "{}/v2/project/{}/version?game_versions=[\"{}\"]",
-                Client::BASE_URL,
-                project_id,
-                game_version
-            )
-        } else {
-            format!("{}/v2/project/{}/version", Client::BASE_URL, project_id)
-        };
-        let response = self.get(&url).await?;
+        let url = format!("{}/v2/project/{}/version", self.base_url, project_id);
+
+        let mut query = Vec::new();
+        if !compatibility.game_versions.is_empty() {
+            let game_versions = serde_json::to_string(&compatibility.game_versions).unwrap();
+            query.push(("game_versions", game_versions));
+        }
+        if !compatibility.loaders.is_empty() {
+            query.push((
+                "loaders",
+                serde_json::to_string(&compatibility.loaders).unwrap(),
+            ));
+        }
+
+        let url = reqwest::Url::parse_with_params(&url, &query).expect("malformed modrinth url");
+        let response = self.get(url.as_str()).await?.error_for_status()?;
+        Ok(response.json().await?)
+    }
+
+    /// Gets a version of a project by either its version number or its id.
+    async fn get_version(&self, project_id: &str, version: &str) -> Result<ProjectVersion> {
+        let mut url = reqwest::Url::parse(&self.base_url).unwrap();
+        url.path_segments_mut()
+            .unwrap()
+            .extend(&["v2", "project", project_id, "version", version]);
+
+        let response = self.get(url.as_str()).await?.error_for_status()?;
+        Ok(response.json().await?)
+    }
+
+    async fn get_version_by_id(&self, version_id: &str) -> Result<ProjectVersion> {
+        let url = format!("{}/v2/version/{}", self.base_url, version_id);
+        let response = self.get(&url).await?.error_for_status()?;
+        Ok(response.json().await?)
+    }
+
+    async fn get_project(&self, project_id: &str) -> Result<Project> {
+        let url = format!("{}/v2/project/{}", self.base_url, project_id);
+        let response = self.get(&url).await?.error_for_status()?;
         Ok(response.json().await?)
     }
 
@@ -101,10 +428,40 @@ impl Client {
     }
 }
 
+#[derive(Deserialize, Debug)]
+pub struct Project {
+    slug: String,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct ProjectVersion {
+    id: String,
+    project_id: String,
     date_published: DateTime<Utc>,
+    version_type: String,
+    #[serde(default = "Default::default")]
+    game_versions: Vec<String>,
+    #[serde(default = "Default::default")]
+    loaders: Vec<String>,
     files: Vec<ProjectFile>,
+    #[serde(default = "Default::default")]
+    dependencies: Vec<ProjectDependency>,
+}
+
+impl ProjectVersion {
+    fn primary_file(&self) -> Option<&ProjectFile> {
+        self.files
+            .iter()
+            .find(|file| file.primary && file.hashes.sha512.is_some())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProjectDependency {
+    version_id: Option<String>,
+    project_id: Option<String>,
+    file_name: Option<String>,
+    dependency_type: String,
 }
 
 #[derive(Deserialize, Debug)]
@@ -115,7 +472,238 @@ pub struct ProjectFile {
     hashes: FileHashes,
 }
 
+impl ProjectFile {
+    fn token(&self) -> cache::Token {
+        cache::Token::Sha512(self.hashes.sha512.clone().unwrap_or_default())
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct FileHashes {
     sha512: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::test_server::TestServer;
+
+    fn version(id: &str, project_id: &str, dependencies: Value) -> Value {
+        json!({
+            "id": id,
+            "project_id": project_id,
+            "date_published": "2024-01-01T00:00:00Z",
+            "version_type": "release",
+            "game_versions": ["1.20.1"],
+            "loaders": ["fabric"],
+            "files": [{
+                "url": format!("https://cdn.modrinth.com/{}.jar", id),
+                "filename": format!("{}.jar", id),
+                "primary": true,
+                "hashes": { "sha512": id },
+            }],
+            "dependencies": dependencies,
+        })
+    }
+
+    fn requires(project_id: &str) -> Value {
+        json!({ "project_id": project_id, "dependency_type": "required" })
+    }
+
+    fn requires_version(project_id: &str, version_id: &str) -> Value {
+        json!({ "project_id": project_id, "version_id": version_id, "dependency_type": "required" })
+    }
+
+    fn project(slug: &str) -> String {
+        json!({ "slug": slug }).to_string()
+    }
+
+    fn versions(versions: &[Value]) -> String {
+        Value::Array(versions.to_vec()).to_string()
+    }
+
+    fn root<'a>(key: &'a str, project_id: &'a str) -> Root<'a> {
+        Root {
+            key,
+            project_id,
+            filter: Filter {
+                game_version: Some("1.20.1".to_owned()),
+                loaders: vec!["fabric".to_owned()],
+                version: None,
+                channel: None,
+            },
+            dependencies: true,
+        }
+    }
+
+    async fn resolve(
+        server: &TestServer,
+        roots: Vec<Root<'_>>,
+        keys: &[&str],
+    ) -> (Vec<String>, Vec<String>) {
+        let client = Client::with_base_url(reqwest::Client::new(), server.url.clone());
+        let keys = keys.iter().map(|key| key.to_string()).collect();
+        let dependencies = resolve_dependencies(&client, roots, &keys).await.unwrap();
+
+        let resolved = dependencies
+            .resolved
+            .into_iter()
+            .map(|dependency| dependency.key)
+            .collect();
+        (resolved, dependencies.problems)
+    }
+
+    #[tokio::test]
+    async fn transitive_dependencies_are_resolved() {
+        let server = TestServer::start([
+            (
+                "/v2/project/A/version",
+                versions(&[version("a1", "A", json!([requires("B")]))]),
+            ),
+            ("/v2/project/B", project("b")),
+            (
+                "/v2/project/B/version",
+                versions(&[version("b1", "B", json!([requires("C"), requires("A")]))]),
+            ),
+            ("/v2/project/C", project("c")),
+            (
+                "/v2/project/C/version",
+                versions(&[version("c1", "C", json!([]))]),
+            ),
+        ])
+        .await;
+
+        let (resolved, problems) = resolve(&server, vec![root("a", "A")], &["a"]).await;
+        assert_eq!(resolved, ["b", "c"]);
+        assert!(problems.is_empty(), "{:?}", problems);
+
+        // dependencies are looked up for the game version and loaders of the source requiring them
+        let requests = server.requests();
+        let lookup = requests
+            .iter()
+            .find(|request| request.starts_with("/v2/project/C/version"))
+            .unwrap();
+        assert!(
+            lookup.contains("game_versions=%5B%221.20.1%22%5D"),
+            "{}",
+            lookup
+        );
+        assert!(lookup.contains("loaders=%5B%22fabric%22%5D"), "{}", lookup);
+    }
+
+    #[tokio::test]
+    async fn conflicting_versions_are_reported() {
+        let server = TestServer::start([
+            (
+                "/v2/project/A/version",
+                versions(&[version("a1", "A", json!([requires_version("B", "b0")]))]),
+            ),
+            (
+                "/v2/project/B/version",
+                versions(&[version("b1", "B", json!([]))]),
+            ),
+        ])
+        .await;
+
+        let (resolved, problems) =
+            resolve(&server, vec![root("a", "A"), root("b", "B")], &["a", "b"]).await;
+        assert!(resolved.is_empty());
+        assert_eq!(
+            problems,
+            ["`a` requires a different version of `b` than the one that is loaded"]
+        );
+    }
+
+    #[tokio::test]
+    async fn projects_provided_by_key_are_not_version_checked() {
+        let server = TestServer::start([
+            (
+                "/v2/project/A/version",
+                versions(&[version("a1", "A", json!([requires_version("B", "b0")]))]),
+            ),
+            (
+                "/v2/project/X/version",
+                versions(&[version("x1", "X", json!([requires_version("B", "b0")]))]),
+            ),
+            ("/v2/project/B", project("b")),
+        ])
+        .await;
+
+        let (resolved, problems) = resolve(
+            &server,
+            vec![root("a", "A"), root("x", "X")],
+            &["a", "x", "b"],
+        )
+        .await;
+        assert!(resolved.is_empty());
+        assert!(problems.is_empty(), "{:?}", problems);
+    }
+
+    #[tokio::test]
+    async fn missing_compatible_versions_are_reported() {
+        let server = TestServer::start([
+            (
+                "/v2/project/A/version",
+                versions(&[version("a1", "A", json!([requires("L")]))]),
+            ),
+            ("/v2/project/L", project("lib")),
+            ("/v2/project/L/version", versions(&[])),
+        ])
+        .await;
+
+        let (resolved, problems) = resolve(&server, vec![root("a", "A")], &["a"]).await;
+        assert!(resolved.is_empty());
+        assert_eq!(
+            problems,
+            ["`a` requires `lib`, but no compatible version was found"]
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_lookups_do_not_stop_resolution() {
+        let server = TestServer::start([
+            (
+                "/v2/project/A/version",
+                versions(&[version("a1", "A", json!([requires("E"), requires("C")]))]),
+            ),
+            ("/v2/project/C", project("c")),
+            (
+                "/v2/project/C/version",
+                versions(&[version("c1", "C", json!([]))]),
+            ),
+        ])
+        .await;
+
+        let (resolved, problems) = resolve(&server, vec![root("a", "A")], &["a"]).await;
+        assert_eq!(resolved, ["c"]);
+        assert_eq!(problems.len(), 1);
+        assert!(
+            problems[0].starts_with("`a` requires `E`, but it could not be looked up"),
+            "{}",
+            problems[0]
+        );
+    }
+
+    #[tokio::test]
+    async fn incompatible_projects_are_reported() {
+        let incompatible = json!({ "project_id": "B", "dependency_type": "incompatible" });
+        let server = TestServer::start([
+            (
+                "/v2/project/A/version",
+                versions(&[version("a1", "A", json!([incompatible]))]),
+            ),
+            (
+                "/v2/project/B/version",
+                versions(&[version("b1", "B", json!([]))]),
+            ),
+        ])
+        .await;
+
+        let (resolved, problems) =
+            resolve(&server, vec![root("a", "A"), root("b", "B")], &["a", "b"]).await;
+        assert!(resolved.is_empty());
+        assert_eq!(problems, ["`a` is incompatible with `b`"]);
+    }
+}
@@ -0,0 +1,417 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use bytes::Bytes;
+use futures::{StreamExt, TryStreamExt};
+use serde::Deserialize;
+use sha1::Sha1;
+use sha2::{Digest, Sha512};
+use zip::ZipArchive;
+
+use crate::config::{self, Source};
+use crate::{cache, source, Context, Error, Result};
+
+const CONCURRENT_DOWNLOADS: usize = 8;
+
+/// Declares where the modpack itself is loaded from: a url, a local path ending in `.mrpack`, or otherwise a Modrinth project.
+pub fn pack_source(
+    mrpack: &str,
+    game_version: &Option<String>,
+    version: &Option<String>,
+    channel: &Option<config::Channel>,
+) -> Source {
+    if mrpack.starts_with("http://") || mrpack.starts_with("https://") {
+        Source::Url {
+            url: mrpack.to_owned(),
+        }
+    } else if mrpack.ends_with(".mrpack") || mrpack.contains('/') {
+        Source::Path {
+            path: PathBuf::from(mrpack),
+        }
+    } else {
+        Source::Modrinth {
+            project_id: mrpack.to_owned(),
+            game_version: game_version.clone(),
+            loaders: Vec::new(),
+            version: version.clone(),
+            channel: *channel,
+            dependencies: false,
+        }
+    }
+}
+
+/// Loads the modpack and all files that it declares for the server, keyed as `<key>/<path>`.
+pub async fn load(
+    ctx: &Context,
+    cache: &mut cache::Loader,
+    key: &str,
+    pack: &Source,
+) -> Result<Vec<(String, cache::Reference)>> {
+    let transform = config::Transform::Direct;
+    let pack_reference =
+        source::load_file(ctx, cache.entry(key).internal(), pack, &transform).await?;
+    let pack = read_pack(pack_reference.read().await?).await?;
+
+    // download everything up-front so that a failed download leaves the cache untouched
+    let downloads = pack
+        .files
+        .iter()
+        .filter(|file| !cache.is_current(&file_key(key, &file.path), &file.token()))
+        .map(|file| {
+            download(
+                ctx.client.clone(),
+                file.path.clone(),
+                file.downloads.clone(),
+                file.hashes.sha512.clone(),
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut downloads: HashMap<String, Bytes> = futures::stream::iter(downloads)
+        .buffer_unordered(CONCURRENT_DOWNLOADS)
+        .try_collect()
+        .await?;
+
+    let mut references = vec![(key.to_owned(), pack_reference)];
+
+    let files = pack.files.into_iter().map(|file| {
+        let token = file.token();
+        let bytes = downloads.remove(&file.path);
+        (file.path, token, bytes)
+    });
+    let overrides = pack.overrides.into_iter().map(|(path, bytes)| {
+        let token = cache::Token::Sha1(Sha1::digest(&bytes).into());
+        (path, token, Some(bytes))
+    });
+
+    for (path, token, bytes) in files.chain(overrides) {
+        let file_key = file_key(key, &path);
+
+        use cache::UpdateResult::*;
+        let reference = match cache.entry(file_key.clone()).try_update(token) {
+            Mismatch(updater) => {
+                let bytes = bytes.ok_or(Error::MissingArtifact)?;
+                let file = source::File { name: path, bytes };
+                updater.update(file).await?
+            }
+            Match(reference) => reference,
+        };
+        references.push((file_key, reference));
+    }
+
+    Ok(references)
+}
+
+fn file_key(key: &str, path: &str) -> String {
+    format!("{}/{}", key, path)
+}
+
+/// Downloads a file of the modpack from the first of its urls that provides it with the expected hash.
+async fn download(
+    client: reqwest::Client,
+    path: String,
+    urls: Vec<String>,
+    sha512: String,
+) -> Result<(String, Bytes)> {
+    let mut result = Err(Error::MissingArtifact);
+
+    for url in &urls {
+        println!("downloading {}...", url);
+
+        result = download_verified(&client, url, &sha512).await;
+        match &result {
+            Ok(_) => break,
+            Err(err) => eprintln!("failed to download {}: {:?}", url, err),
+        }
+    }
+
+    result.map(|bytes| (path, bytes))
+}
+
+async fn download_verified(client: &reqwest::Client, url: &str, sha512: &str) -> Result<Bytes> {
+    let response = client.get(url).send().await?.error_for_status()?;
+    let bytes = response.bytes().await?;
+
+    if hex::encode(Sha512::digest(&bytes)) == sha512.to_lowercase() {
+        Ok(bytes)
+    } else {
+        Err(Error::ChecksumMismatch(url.to_owned()))
+    }
+}
+
+struct Pack {
+    files: Vec<PackFile>,
+    overrides: Vec<(String, Bytes)>,
+}
+
+async fn read_pack(bytes: Vec<u8>) -> Result<Pack> {
+    tokio::task::spawn_blocking(move || {
+        let mut zip = ZipArchive::new(io::Cursor::new(bytes))?;
+
+        let index: PackIndex = {
+            let file = zip.by_name("modrinth.index.json")?;
+            serde_json::from_reader(file).map_err(|err| Error::MalformedModpack(err.to_string()))?
+        };
+        println!(
+            "loading modpack {} {} for {:?}",
+            index.name, index.version_id, index.dependencies
+        );
+
+        // server overrides are applied after the common overrides, replacing them where both declare a file
+        let mut overrides = HashMap::new();
+        let mut server_overrides = HashMap::new();
+
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i)?;
+            if !file.is_file() {
+                continue;
+            }
+
+            let name = file.name().to_owned();
+            let (target, path) = if let Some(path) = name.strip_prefix("overrides/") {
+                (&mut overrides, path)
+            } else if let Some(path) = name.strip_prefix("server-overrides/") {
+                (&mut server_overrides, path)
+            } else {
+                continue;
+            };
+
+            if !is_safe_path(path) {
+                eprintln!(
+                    "Warning: skipping modpack override with unsafe path {}",
+                    name
+                );
+                continue;
+            }
+
+            let mut bytes = Vec::with_capacity(file.size() as usize);
+            file.read_to_end(&mut bytes)?;
+            target.insert(path.to_owned(), Bytes::from(bytes));
+        }
+        overrides.extend(server_overrides);
+
+        let files = index
+            .files
+            .into_iter()
+            .filter(|file| file.is_server_supported())
+            .filter(|file| !overrides.contains_key(&file.path))
+            .filter(|file| {
+                let safe = is_safe_path(&file.path);
+                if !safe {
+                    eprintln!(
+                        "Warning: skipping modpack file with unsafe path {}",
+                        file.path
+                    );
+                }
+                safe
+            })
+            .collect();
+
+        Ok(Pack {
+            files,
+            overrides: overrides.into_iter().collect(),
+        })
+    })
+    .await
+    .unwrap()
+}
+
+/// Modpacks must not be able to place files outside of the destination.
+fn is_safe_path(path: &str) -> bool {
+    let path = Path::new(path);
+    path.components().next().is_some()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+#[allow(unused)]
+struct PackIndex {
+    format_version: u32,
+    game: String,
+    version_id: String,
+    name: String,
+    files: Vec<PackFile>,
+    dependencies: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PackFile {
+    path: String,
+    hashes: PackHashes,
+    env: Option<PackEnv>,
+    downloads: Vec<String>,
+}
+
+impl PackFile {
+    fn is_server_supported(&self) -> bool {
+        self.env
+            .as_ref()
+            .map(|env| env.server != "unsupported")
+            .unwrap_or(true)
+    }
+
+    fn token(&self) -> cache::Token {
+        cache::Token::Sha512(self.hashes.sha512.to_lowercase())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct PackHashes {
+    sha512: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct PackEnv {
+    server: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use serde_json::json;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    use super::*;
+    use crate::test_server::TestServer;
+
+    fn pack_file(path: &str, server: Option<&str>) -> serde_json::Value {
+        json!({
+            "path": path,
+            "hashes": { "sha1": "", "sha512": "" },
+            "env": server.map(|server| json!({ "client": "required", "server": server })),
+            "downloads": [format!("https://cdn.modrinth.com/{}", path)],
+        })
+    }
+
+    fn write_pack(files: Vec<serde_json::Value>, overrides: &[(&str, &str)]) -> Vec<u8> {
+        let index = json!({
+            "formatVersion": 1,
+            "game": "minecraft",
+            "versionId": "1.0.0",
+            "name": "Test Pack",
+            "files": files,
+            "dependencies": { "minecraft": "1.20.1", "fabric-loader": "0.15.0" },
+        });
+
+        let mut zip = ZipWriter::new(io::Cursor::new(Vec::new()));
+        zip.start_file("modrinth.index.json", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(index.to_string().as_bytes()).unwrap();
+        for (path, contents) in overrides {
+            zip.start_file(*path, SimpleFileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn paths(pack: &Pack) -> Vec<&str> {
+        let mut paths: Vec<&str> = pack.files.iter().map(|file| file.path.as_str()).collect();
+        paths.sort();
+        paths
+    }
+
+    fn override_contents<'a>(pack: &'a Pack, path: &str) -> Option<&'a [u8]> {
+        pack.overrides
+            .iter()
+            .find(|(override_path, _)| override_path == path)
+            .map(|(_, bytes)| bytes.as_ref())
+    }
+
+    #[test]
+    fn unsafe_paths_are_rejected() {
+        assert!(is_safe_path("mods/lithium.jar"));
+        assert!(is_safe_path("config/lithium.properties"));
+
+        assert!(!is_safe_path(""));
+        assert!(!is_safe_path("../server.jar"));
+        assert!(!is_safe_path("mods/../../server.jar"));
+        assert!(!is_safe_path("./mods/lithium.jar"));
+        assert!(!is_safe_path("/etc/passwd"));
+    }
+
+    #[tokio::test]
+    async fn server_overrides_take_precedence() {
+        let bytes = write_pack(
+            vec![
+                pack_file("config/a.txt", None),
+                pack_file("mods/a.jar", None),
+            ],
+            &[
+                ("overrides/config/a.txt", "common"),
+                ("overrides/config/b.txt", "common"),
+                ("server-overrides/config/a.txt", "server"),
+                ("client-overrides/config/c.txt", "client"),
+            ],
+        );
+        let pack = read_pack(bytes).await.unwrap();
+
+        assert_eq!(
+            override_contents(&pack, "config/a.txt"),
+            Some(&b"server"[..])
+        );
+        assert_eq!(
+            override_contents(&pack, "config/b.txt"),
+            Some(&b"common"[..])
+        );
+        assert_eq!(override_contents(&pack, "config/c.txt"), None);
+
+        // files replaced by an override are not downloaded
+        assert_eq!(paths(&pack), ["mods/a.jar"]);
+    }
+
+    #[tokio::test]
+    async fn client_only_and_unsafe_files_are_skipped() {
+        let bytes = write_pack(
+            vec![
+                pack_file("mods/server.jar", Some("required")),
+                pack_file("mods/optional.jar", Some("optional")),
+                pack_file("mods/client.jar", Some("unsupported")),
+                pack_file("mods/any.jar", None),
+                pack_file("../escape.jar", None),
+                pack_file("/etc/escape.jar", None),
+            ],
+            &[("overrides/../escape.txt", "escape")],
+        );
+        let pack = read_pack(bytes).await.unwrap();
+
+        assert_eq!(
+            paths(&pack),
+            ["mods/any.jar", "mods/optional.jar", "mods/server.jar"]
+        );
+        assert!(pack.overrides.is_empty());
+    }
+
+    #[tokio::test]
+    async fn downloads_are_verified() {
+        let server = TestServer::start([
+            ("/tampered.jar", "tampered".to_owned()),
+            ("/mod.jar", "mod".to_owned()),
+        ])
+        .await;
+        let client = reqwest::Client::new();
+        let sha512 = hex::encode(Sha512::digest(b"mod"));
+
+        let tampered = format!("{}/tampered.jar", server.url);
+        let result = download_verified(&client, &tampered, &sha512).await;
+        assert!(matches!(result, Err(Error::ChecksumMismatch(url)) if url == tampered));
+
+        // the next url is tried when one does not provide the expected file
+        let urls = vec![tampered, format!("{}/mod.jar", server.url)];
+        let (path, bytes) = download(
+            client,
+            "mods/mod.jar".to_owned(),
+            urls,
+            sha512.to_uppercase(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(path, "mods/mod.jar");
+        assert_eq!(bytes, "mod");
+    }
+}
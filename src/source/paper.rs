@@ -0,0 +1,173 @@
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{cache, config, source, Error, Result};
+
+const BASE_URL: &str = "https://api.papermc.io/v2/projects/paper";
+
+const SERVER_NAME: &str = "paper.jar";
+
+/// How many of the newest releases are checked for a stable build when loading the latest version.
+const MAX_LATEST_VERSIONS: usize = 5;
+
+pub async fn load<'a>(
+    client: &reqwest::Client,
+    cache: cache::Entry<'a>,
+    version: &str,
+    build: Option<u32>,
+    transform: &config::Transform,
+) -> Result<cache::Reference> {
+    let build = resolve_build(client, version, build).await?;
+
+    if let Some((version, build)) = build {
+        use cache::UpdateResult::*;
+        match cache.try_update(build.token()) {
+            Mismatch(updater) => {
+                let application = &build.downloads.application;
+                let url = format!(
+                    "{}/versions/{}/builds/{}/downloads/{}",
+                    BASE_URL, version, build.build, application.name
+                );
+                println!("downloading {}...", url);
+
+                let response = client.get(&url).send().await?.error_for_status()?;
+                let bytes = response.bytes().await?;
+
+                if !application
+                    .sha256
+                    .eq_ignore_ascii_case(&hex::encode(Sha256::digest(&bytes)))
+                {
+                    return Err(Error::ChecksumMismatch(url));
+                }
+
+                let file = source::File {
+                    name: SERVER_NAME.to_owned(),
+                    bytes,
+                };
+
+                if let Some(file) = transform.apply(file).await? {
+                    Ok(updater.update(file).await?)
+                } else {
+                    Err(Error::MissingArtifact)
+                }
+            }
+            Match(reference) => Ok(reference),
+        }
+    } else {
+        cache.get_existing().ok_or(Error::MissingArtifact)
+    }
+}
+
+pub async fn resolve(
+    client: &reqwest::Client,
+    version: &str,
+    build: Option<u32>,
+) -> Result<Option<cache::Token>> {
+    let build = resolve_build(client, version, build).await?;
+    Ok(build.map(|(_, build)| build.token()))
+}
+
+/// Finds the given build, or otherwise the newest stable build, of the given version, where `latest` selects the newest
+/// release that has a stable build.
+async fn resolve_build(
+    client: &reqwest::Client,
+    version: &str,
+    build: Option<u32>,
+) -> Result<Option<(String, Build)>> {
+    let versions: Vec<String> = if version == "latest" {
+        // versions are listed oldest first, and new releases may only have experimental builds for a while
+        let project: Project = get(client, BASE_URL).await?;
+        project
+            .versions
+            .into_iter()
+            .rev()
+            .filter(|version| is_release(version))
+            .take(MAX_LATEST_VERSIONS)
+            .collect()
+    } else {
+        vec![version.to_owned()]
+    };
+
+    for candidate_version in versions {
+        let url = format!("{}/versions/{}/builds", BASE_URL, candidate_version);
+        let builds: Builds = get(client, &url).await?;
+
+        // builds are listed oldest first, and only builds on the default channel are considered stable
+        let mut builds = builds.builds.into_iter().rev();
+        let found = match build {
+            Some(build) => builds.find(|candidate| candidate.build == build),
+            None => builds.find(|candidate| candidate.channel == "default"),
+        };
+
+        if let Some(found) = found {
+            return Ok(Some((candidate_version, found)));
+        }
+    }
+
+    eprintln!("Warning: no stable paper build found for {}", version);
+    Ok(None)
+}
+
+/// Pre-releases and release candidates are named like `1.21-pre1` or `1.20.5-rc1`, unlike releases.
+fn is_release(version: &str) -> bool {
+    version
+        .split('.')
+        .all(|part| !part.is_empty() && part.bytes().all(|byte| byte.is_ascii_digit()))
+}
+
+async fn get<T: serde::de::DeserializeOwned>(client: &reqwest::Client, url: &str) -> Result<T> {
+    let response = client.get(url).send().await?.error_for_status()?;
+    Ok(response.json().await?)
+}
+
+#[derive(Deserialize, Debug)]
+struct Project {
+    versions: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Builds {
+    builds: Vec<Build>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Build {
+    build: u32,
+    channel: String,
+    downloads: BuildDownloads,
+}
+
+impl Build {
+    fn token(&self) -> cache::Token {
+        cache::Token::Sha256(self.downloads.application.sha256.to_lowercase())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct BuildDownloads {
+    application: BuildDownload,
+}
+
+#[derive(Deserialize, Debug)]
+struct BuildDownload {
+    name: String,
+    sha256: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_numbered_versions_are_releases() {
+        assert!(is_release("1.21"));
+        assert!(is_release("1.20.6"));
+
+        assert!(!is_release("1.21-pre1"));
+        assert!(!is_release("1.20.5-rc1"));
+        assert!(!is_release("24w14a"));
+        assert!(!is_release("1..2"));
+        assert!(!is_release("1.21."));
+        assert!(!is_release(""));
+    }
+}
@@ -13,14 +13,8 @@ pub async fn load<'a>(
 ) -> Result<cache::Reference> {
     let bytes = fs::read(&path).await?;
 
-    let mut hasher = Sha1::new();
-    hasher.update(&bytes);
-
-    let mut hash = [0u8; 20];
-    hash.copy_from_slice(&hasher.finalize());
-
     use cache::UpdateResult::*;
-    match cache.try_update(cache::Token::Sha1(hash)) {
+    match cache.try_update(cache::Token::Sha1(hash(&bytes))) {
         Mismatch(updater) => {
             let name = path
                 .file_name()
@@ -41,3 +35,17 @@ pub async fn load<'a>(
         Match(reference) => Ok(reference),
     }
 }
+
+pub async fn resolve(path: &PathBuf) -> Result<Option<cache::Token>> {
+    let bytes = fs::read(&path).await?;
+    Ok(Some(cache::Token::Sha1(hash(&bytes))))
+}
+
+fn hash(bytes: &[u8]) -> [u8; 20] {
+    let mut hasher = Sha1::new();
+    hasher.update(bytes);
+
+    let mut hash = [0u8; 20];
+    hash.copy_from_slice(&hasher.finalize());
+    hash
+}
@@ -0,0 +1,131 @@
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+
+use crate::{cache, config, source, Error, Result};
+
+const MANIFEST_URL: &str = "https://piston-meta.mojang.com/mc/game/version_manifest_v2.json";
+
+const SERVER_NAME: &str = "server.jar";
+
+pub async fn load<'a>(
+    client: &reqwest::Client,
+    cache: cache::Entry<'a>,
+    version: &str,
+    transform: &config::Transform,
+) -> Result<cache::Reference> {
+    let download = resolve_download(client, version).await?;
+
+    if let Some(download) = download {
+        use cache::UpdateResult::*;
+        match cache.try_update(download.token()) {
+            Mismatch(updater) => {
+                println!("downloading {}...", download.url);
+
+                let response = client.get(&download.url).send().await?.error_for_status()?;
+                let bytes = response.bytes().await?;
+
+                if !download
+                    .sha1
+                    .eq_ignore_ascii_case(&hex::encode(Sha1::digest(&bytes)))
+                {
+                    return Err(Error::ChecksumMismatch(download.url));
+                }
+
+                let file = source::File {
+                    name: SERVER_NAME.to_owned(),
+                    bytes,
+                };
+
+                if let Some(file) = transform.apply(file).await? {
+                    Ok(updater.update(file).await?)
+                } else {
+                    Err(Error::MissingArtifact)
+                }
+            }
+            Match(reference) => Ok(reference),
+        }
+    } else {
+        cache.get_existing().ok_or(Error::MissingArtifact)
+    }
+}
+
+pub async fn resolve(client: &reqwest::Client, version: &str) -> Result<Option<cache::Token>> {
+    let download = resolve_download(client, version).await?;
+    Ok(download.map(|download| download.token()))
+}
+
+/// Finds the server download of the given version, where `latest` and `snapshot` select the newest release or snapshot.
+async fn resolve_download(client: &reqwest::Client, version: &str) -> Result<Option<Download>> {
+    let manifest: Manifest = get(client, MANIFEST_URL).await?;
+
+    let id = match version {
+        "latest" => &manifest.latest.release,
+        "snapshot" => &manifest.latest.snapshot,
+        version => version,
+    };
+
+    let version = match manifest.versions.iter().find(|version| version.id == id) {
+        Some(version) => version,
+        None => {
+            eprintln!("Warning: minecraft version {} does not exist", id);
+            return Ok(None);
+        }
+    };
+
+    let package: Package = get(client, &version.url).await?;
+    let download = package.downloads.server;
+    if download.is_none() {
+        eprintln!("Warning: minecraft version {} has no server download", id);
+    }
+
+    Ok(download)
+}
+
+async fn get<T: serde::de::DeserializeOwned>(client: &reqwest::Client, url: &str) -> Result<T> {
+    let response = client.get(url).send().await?.error_for_status()?;
+    Ok(response.json().await?)
+}
+
+#[derive(Deserialize, Debug)]
+struct Manifest {
+    latest: LatestVersions,
+    versions: Vec<ManifestVersion>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LatestVersions {
+    release: String,
+    snapshot: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ManifestVersion {
+    id: String,
+    url: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Package {
+    downloads: PackageDownloads,
+}
+
+#[derive(Deserialize, Debug)]
+struct PackageDownloads {
+    server: Option<Download>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Download {
+    sha1: String,
+    url: String,
+}
+
+impl Download {
+    fn token(&self) -> cache::Token {
+        let mut hash = [0u8; 20];
+        match hex::decode_to_slice(&self.sha1, &mut hash) {
+            Ok(()) => cache::Token::Sha1(hash),
+            Err(_) => cache::Token::Unknown,
+        }
+    }
+}
@@ -13,17 +13,39 @@ impl StatusWriter {
     }
 
     pub fn write(&self, message: impl Into<webhook::Payload>) {
-        if let Some(webhook) = &self.webhook {
-            let webhook = webhook.clone();
-            let payload = message.into();
+        self.spawn_post(message.into(), None);
+    }
+
+    pub fn write_with_attachment(
+        &self,
+        message: impl Into<webhook::Payload>,
+        attachment: webhook::Attachment,
+    ) {
+        self.spawn_post(message.into(), Some(attachment));
+    }
+
+    /// Posts the given message and waits for it to be sent, useful when the wrapper is about to exit.
+    pub async fn send(&self, message: impl Into<webhook::Payload>) {
+        self.post(&message.into(), None).await;
+    }
 
-            tokio::spawn(async move {
-                let result = webhook.post(&payload).await;
+    fn spawn_post(&self, payload: webhook::Payload, attachment: Option<webhook::Attachment>) {
+        if self.webhook.is_some() {
+            let status = self.clone();
+            tokio::spawn(async move { status.post(&payload, attachment.as_ref()).await });
+        }
+    }
+
+    async fn post(&self, payload: &webhook::Payload, attachment: Option<&webhook::Attachment>) {
+        if let Some(webhook) = &self.webhook {
+            let result = match attachment {
+                Some(attachment) => webhook.post_with_attachment(payload, attachment).await,
+                None => webhook.post(payload).await,
+            };
 
-                if let Err(err) = result {
-                    eprintln!("failed to post to webhook: {:?}", err);
-                }
-            });
+            if let Err(err) = result {
+                eprintln!("failed to post to webhook: {:?}", err);
+            }
         }
     }
 }
@@ -31,6 +31,12 @@ impl<T: Into<String>> From<T> for Payload {
     }
 }
 
+/// A file uploaded together with a payload.
+pub struct Attachment {
+    pub file_name: String,
+    pub bytes: Vec<u8>,
+}
+
 #[derive(Serialize)]
 pub struct AllowedMentions {
     pub parse: Vec<String>,
@@ -90,4 +96,20 @@ impl Client {
         self.reqwest.post(&self.url).json(payload).send().await?;
         Ok(())
     }
+
+    pub async fn post_with_attachment(
+        &self,
+        payload: &Payload,
+        attachment: &Attachment,
+    ) -> reqwest::Result<()> {
+        let payload_json = serde_json::to_string(payload).expect("malformed payload");
+        let file = reqwest::multipart::Part::bytes(attachment.bytes.clone())
+            .file_name(attachment.file_name.clone());
+        let form = reqwest::multipart::Form::new()
+            .text("payload_json", payload_json)
+            .part("files[0]", file);
+
+        self.reqwest.post(&self.url).multipart(form).send().await?;
+        Ok(())
+    }
 }
@@ -0,0 +1,48 @@
+//! Fixtures shared by tests that work with files on disk.
+
+use std::path::{Path, PathBuf};
+
+use crate::{cache, source};
+
+/// A directory in the system temp directory that is removed when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Creates an empty directory, whose name has to be unique among all tests as they run concurrently.
+    pub fn new(name: &str) -> TempDir {
+        let path =
+            std::env::temp_dir().join(format!("server-wrapper-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Caches a file with the given token and contents under the key, as if a source had loaded it before.
+pub async fn seed(
+    loader: &mut cache::Loader,
+    key: &str,
+    token: cache::Token,
+    contents: &str,
+) -> cache::Reference {
+    match loader.entry(key).try_update(token) {
+        cache::UpdateResult::Mismatch(updater) => {
+            let file = source::File {
+                name: key.to_owned(),
+                bytes: contents.to_owned().into(),
+            };
+            updater.update(file).await.unwrap()
+        }
+        cache::UpdateResult::Match(_) => panic!("{} is already cached with the same token", key),
+    }
+}
@@ -0,0 +1,119 @@
+//! A local HTTP server serving canned responses, used to test sources without reaching their real APIs.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+pub struct TestServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+    task: JoinHandle<()>,
+}
+
+impl TestServer {
+    /// Serves each body at its path, ignoring the query. Any other path is answered with a 404.
+    /// Occurrences of `{url}` in the bodies are replaced with the url of the server, for links back to it.
+    pub async fn start<'a>(routes: impl IntoIterator<Item = (&'a str, String)>) -> TestServer {
+        let (listener, url) = bind().await;
+
+        let routes: HashMap<String, Bytes> = routes
+            .into_iter()
+            .map(|(path, body)| (path.to_owned(), Bytes::from(body.replace("{url}", &url))))
+            .collect();
+
+        serve(listener, url, move |request| route(&routes, request))
+    }
+
+    /// Answers every request with the given handler, for responses that depend on the request headers.
+    pub async fn with_handler<H>(handler: H) -> TestServer
+    where
+        H: Fn(&Request<Incoming>) -> Response<Full<Bytes>> + Send + Sync + 'static,
+    {
+        let (listener, url) = bind().await;
+        serve(listener, url, handler)
+    }
+
+    /// The paths and queries of all requests received so far, in order.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn bind() -> (TcpListener, String) {
+    let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .await
+        .expect("failed to bind test server");
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    (listener, url)
+}
+
+fn serve<H>(listener: TcpListener, url: String, handler: H) -> TestServer
+where
+    H: Fn(&Request<Incoming>) -> Response<Full<Bytes>> + Send + Sync + 'static,
+{
+    let handler = Arc::new(handler);
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let task = tokio::spawn({
+        let requests = requests.clone();
+        async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let handler = handler.clone();
+                let requests = requests.clone();
+
+                tokio::spawn(async move {
+                    let service = service_fn(|request| {
+                        requests.lock().unwrap().push(
+                            request
+                                .uri()
+                                .path_and_query()
+                                .map(|path| path.to_string())
+                                .unwrap_or_default(),
+                        );
+
+                        let response = handler(&request);
+                        async move { Ok::<_, Infallible>(response) }
+                    });
+
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        }
+    });
+
+    TestServer {
+        url,
+        requests,
+        task,
+    }
+}
+
+fn route(routes: &HashMap<String, Bytes>, request: &Request<Incoming>) -> Response<Full<Bytes>> {
+    match routes.get(request.uri().path()) {
+        Some(body) => Response::new(Full::new(body.clone())),
+        None => {
+            let mut response = Response::new(Full::new(Bytes::from_static(b"not found")));
+            *response.status_mut() = StatusCode::NOT_FOUND;
+            response
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::path::Path;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+
+use crate::config::{self, Config, Trigger};
+use crate::Context;
+
+pub mod poll;
+pub mod schedule;
+pub mod webhook;
+
+/// Sent to the supervisor whenever a trigger fires while the server is running.
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub trigger: String,
+    pub action: config::TriggerAction,
+}
+
+/// Starts listening for all triggers that can fire while the server is running.
+pub fn spawn(config: &Config, destinations_path: &str) -> mpsc::UnboundedReceiver<Event> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    for (name, trigger) in &config.triggers {
+        match trigger {
+            Trigger::Startup => (),
+            Trigger::Webhook {
+                port,
+                token,
+                action,
+            } => {
+                let event = Event {
+                    trigger: name.clone(),
+                    action: *action,
+                };
+                let listener = webhook::Listener {
+                    port: *port,
+                    token: token.clone(),
+                    event,
+                    sender: sender.clone(),
+                };
+
+                let name = name.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = listener.run().await {
+                        eprintln!("webhook trigger '{}' failed: {:?}", name, err);
+                    }
+                });
+            }
+            Trigger::Poll {
+                interval_seconds,
+                action,
+            } => {
+                let event = Event {
+                    trigger: name.clone(),
+                    action: *action,
+                };
+                let poll = poll::Poll {
+                    interval: Duration::from_secs(*interval_seconds),
+                    ctx: Context::new(config),
+                    destinations_path: Path::new(destinations_path).to_owned(),
+                    event,
+                    sender: sender.clone(),
+                };
+
+                tokio::spawn(poll.run());
+            }
+            Trigger::Schedule { cron, action } => {
+                let cron = match croner::Cron::new(cron).with_seconds_optional().parse() {
+                    Ok(cron) => cron,
+                    Err(err) => {
+                        eprintln!("invalid cron expression for trigger '{}': {:?}", name, err);
+                        continue;
+                    }
+                };
+
+                let event = Event {
+                    trigger: name.clone(),
+                    action: *action,
+                };
+                let schedule = schedule::Schedule {
+                    cron,
+                    event,
+                    sender: sender.clone(),
+                };
+
+                let name = name.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = schedule.run().await {
+                        eprintln!("schedule trigger '{}' failed: {:?}", name, err);
+                    }
+                });
+            }
+        }
+    }
+
+    receiver
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+
+use super::Event;
+use crate::{cache, config, Context};
+
+pub struct Poll {
+    pub interval: Duration,
+    pub ctx: Context,
+    pub destinations_path: PathBuf,
+    pub event: Event,
+    pub sender: mpsc::UnboundedSender<Event>,
+}
+
+impl Poll {
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(self.interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        // the first tick completes immediately, but sources were only just loaded on startup
+        interval.tick().await;
+
+        // the tokens that were last reported for each (destination, key), so that a version which then fails to load
+        // is not reported over and over again
+        let mut reported = HashMap::new();
+
+        loop {
+            interval.tick().await;
+
+            if self.has_changed(&mut reported).await
+                && self.sender.send(self.event.clone()).is_err()
+            {
+                return;
+            }
+        }
+    }
+
+    async fn has_changed(&self, reported: &mut HashMap<(String, String), cache::Token>) -> bool {
+        // reload destinations every time so that we pick up any changes made to them
+        let destinations: config::Destinations = config::load(&self.destinations_path).await;
+        let destinations = crate::select_destinations(&destinations, &[self.event.trigger.as_str()]);
+
+        let mut changed = false;
+
+        for (name, destination) in &destinations {
+            match crate::check_destination(&self.ctx, name, destination).await {
+                Ok(outdated) => {
+                    let outdated = unreported(reported, name, outdated);
+                    if !outdated.is_empty() {
+                        println!(
+                            "poll trigger '{}' found changes in {}: {}",
+                            self.event.trigger,
+                            name,
+                            outdated.join(", ")
+                        );
+                        changed = true;
+                    }
+                }
+                Err(err) => eprintln!("failed to check destination '{}': {:?}", name, err),
+            }
+        }
+
+        changed
+    }
+}
+
+/// Filters out the keys whose newer version was already reported, remembering the versions of the others.
+fn unreported(
+    reported: &mut HashMap<(String, String), cache::Token>,
+    destination: &str,
+    outdated: Vec<(String, cache::Token)>,
+) -> Vec<String> {
+    outdated
+        .into_iter()
+        .filter(|(key, token)| {
+            let previous = reported.insert((destination.to_owned(), key.clone()), token.clone());
+            previous.as_ref() != Some(token)
+        })
+        .map(|(key, _)| key)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outdated(version: &str) -> Vec<(String, cache::Token)> {
+        vec![("mod".to_owned(), cache::Token::Version(version.to_owned()))]
+    }
+
+    #[test]
+    fn versions_are_only_reported_once() {
+        let mut reported = HashMap::new();
+
+        assert_eq!(unreported(&mut reported, "mods", outdated("1")), ["mod"]);
+        // the version failed to load, so the cache is still outdated but the trigger should not fire again
+        assert!(unreported(&mut reported, "mods", outdated("1")).is_empty());
+        assert_eq!(unreported(&mut reported, "mods", outdated("2")), ["mod"]);
+        assert_eq!(unreported(&mut reported, "other", outdated("2")), ["mod"]);
+    }
+}
@@ -0,0 +1,36 @@
+use chrono::Local;
+use croner::errors::CronError;
+use croner::Cron;
+use tokio::sync::mpsc;
+
+use super::Event;
+
+pub struct Schedule {
+    pub cron: Cron,
+    pub event: Event,
+    pub sender: mpsc::UnboundedSender<Event>,
+}
+
+impl Schedule {
+    pub async fn run(self) -> Result<(), CronError> {
+        let mut last = Local::now();
+        loop {
+            // the wall clock may lag slightly behind the timer, so never schedule before the last occurrence
+            let now = Local::now().max(last);
+            let next = self.cron.find_next_occurrence(&now, false)?;
+            last = next;
+            println!(
+                "schedule trigger '{}' will fire at {}",
+                self.event.trigger, next
+            );
+
+            let delay = (next - now).to_std().unwrap_or_default();
+            tokio::time::sleep(delay).await;
+
+            println!("schedule trigger '{}' fired", self.event.trigger);
+            if self.sender.send(self.event.clone()).is_err() {
+                return Ok(());
+            }
+        }
+    }
+}
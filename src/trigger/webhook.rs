@@ -0,0 +1,130 @@
+use std::convert::Infallible;
+use std::io;
+use std::net::SocketAddr;
+
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::body::Incoming;
+use hyper::header::HeaderMap;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+use super::Event;
+
+pub struct Listener {
+    pub port: u16,
+    pub token: Option<String>,
+    pub event: Event,
+    pub sender: mpsc::UnboundedSender<Event>,
+}
+
+impl Listener {
+    pub async fn run(self) -> io::Result<()> {
+        let listener = TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], self.port))).await?;
+        println!(
+            "listening for webhook trigger '{}' on port {}",
+            self.event.trigger, self.port
+        );
+
+        loop {
+            let (stream, _) = listener.accept().await?;
+
+            let token = self.token.clone();
+            let event = self.event.clone();
+            let sender = self.sender.clone();
+
+            tokio::spawn(async move {
+                let service = service_fn(|request| {
+                    let response = handle(&request, &token, &event, &sender);
+                    async move { Ok::<_, Infallible>(response) }
+                });
+
+                let result = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+                if let Err(err) = result {
+                    eprintln!("failed to serve webhook request: {:?}", err);
+                }
+            });
+        }
+    }
+}
+
+fn handle(
+    request: &Request<Incoming>,
+    token: &Option<String>,
+    event: &Event,
+    sender: &mpsc::UnboundedSender<Event>,
+) -> Response<Full<Bytes>> {
+    if request.method() != Method::POST {
+        return respond(StatusCode::METHOD_NOT_ALLOWED, "expected POST");
+    }
+
+    if !is_authorized(request.headers(), token) {
+        return respond(StatusCode::UNAUTHORIZED, "invalid token");
+    }
+
+    println!("received webhook trigger '{}'", event.trigger);
+
+    match sender.send(event.clone()) {
+        Ok(_) => respond(StatusCode::ACCEPTED, "accepted"),
+        Err(_) => respond(StatusCode::SERVICE_UNAVAILABLE, "wrapper is shutting down"),
+    }
+}
+
+/// Requests must provide the token as a bearer token, if one is configured.
+fn is_authorized(headers: &HeaderMap, token: &Option<String>) -> bool {
+    match token {
+        Some(token) => {
+            let authorization = headers
+                .get(hyper::header::AUTHORIZATION)
+                .and_then(|authorization| authorization.to_str().ok())
+                .and_then(|authorization| authorization.strip_prefix("Bearer "));
+            authorization == Some(token.as_str())
+        }
+        None => true,
+    }
+}
+
+fn respond(status: StatusCode, message: &'static str) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::from(message)));
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(authorization: Option<&str>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(authorization) = authorization {
+            headers.insert(hyper::header::AUTHORIZATION, authorization.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn bearer_token_must_match() {
+        let token = Some("secret".to_owned());
+
+        assert!(is_authorized(&headers(Some("Bearer secret")), &token));
+
+        assert!(!is_authorized(&headers(None), &token));
+        assert!(!is_authorized(&headers(Some("Bearer other")), &token));
+        assert!(!is_authorized(&headers(Some("Bearer secret2")), &token));
+        assert!(!is_authorized(&headers(Some("secret")), &token));
+        assert!(!is_authorized(&headers(Some("Basic secret")), &token));
+        assert!(!is_authorized(&headers(Some("Bearer ")), &token));
+    }
+
+    #[test]
+    fn requests_are_authorized_without_token() {
+        assert!(is_authorized(&headers(None), &None));
+        assert!(is_authorized(&headers(Some("Bearer anything")), &None));
+    }
+}